@@ -1,14 +1,15 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
 #[derive(Parser)]
 pub struct Cli {
     /// патерн для поиска
     pub pattern: String,
 
-    /// искать паттерн в файле
-    pub file: PathBuf,
+    /// искать паттерн в файлах
+    #[clap(required = true)]
+    pub files: Vec<PathBuf>,
 
     /// печатать +N строк после совпадения
     #[clap(short = 'A', default_value_t = 0)]
@@ -23,7 +24,7 @@ pub struct Cli {
     pub context: u32,
 
     /// печатать количество строк
-    #[clap(short)]
+    #[clap(short, conflicts_with = "json")]
     pub count: bool,
 
     /// игнорировать регистр
@@ -41,4 +42,32 @@ pub struct Cli {
     /// печатать номер строки
     #[clap(short = 'n')]
     pub line_number: bool,
+
+    /// остановиться после N совпадений
+    #[clap(short = 'm')]
+    pub max_count: Option<usize>,
+
+    /// ничего не печатать, выйти при первом совпадении
+    #[clap(short, conflicts_with = "json")]
+    pub quiet: bool,
+
+    /// печатать результат в формате JSON Lines
+    #[clap(long)]
+    pub json: bool,
+
+    /// количество потоков для поиска (по умолчанию - по числу ядер)
+    #[clap(short = 'j', long, value_parser = clap::value_parser!(u16).range(1..))]
+    pub threads: Option<u16>,
+
+    /// порядок вывода файлов
+    #[clap(long, value_enum, default_value = "none")]
+    pub sort: Sort,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Sort {
+    /// в порядке завершения поиска
+    None,
+    /// по пути к файлу
+    Path,
 }
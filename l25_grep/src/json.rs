@@ -0,0 +1,137 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+/// One line of JSON output.
+/// Each searched file is wrapped into [`Event::Begin`] and [`Event::End`].
+#[derive(Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum Event<'a> {
+    Begin {
+        path: &'a str,
+    },
+    Match {
+        path: &'a str,
+        #[serde(flatten)]
+        line: Line<'a>,
+        submatches: Vec<Submatch<'a>>,
+    },
+    Context {
+        path: &'a str,
+        #[serde(flatten)]
+        line: Line<'a>,
+    },
+    End {
+        path: &'a str,
+        stats: Stats,
+    },
+}
+
+#[derive(Serialize)]
+pub struct Line<'a> {
+    /// starts with 1
+    pub line_number: usize,
+    /// offset of the first byte of line from start of file
+    pub absolute_offset: usize,
+    pub text: &'a str,
+}
+
+/// Matched part of line, `start` and `end` are byte offsets within line.
+#[derive(Serialize)]
+pub struct Submatch<'a> {
+    #[serde(rename = "match")]
+    pub text: &'a str,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Serialize, Default)]
+pub struct Stats {
+    /// lines printed as matches
+    pub matched_lines: usize,
+    /// all submatches within matched lines
+    pub matches: usize,
+}
+
+impl Event<'_> {
+    pub fn write(&self, out: &mut impl Write) -> Result<(), io::Error> {
+        serde_json::to_writer(&mut *out, self)?;
+        writeln!(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_string(event: Event) -> String {
+        let mut out = Vec::new();
+        event.write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn line(text: &str) -> Line<'_> {
+        Line {
+            line_number: 2,
+            absolute_offset: 4,
+            text,
+        }
+    }
+
+    #[test]
+    fn events() {
+        assert_eq!(
+            to_string(Event::Begin { path: "a.txt" }),
+            "{\"type\":\"begin\",\"data\":{\"path\":\"a.txt\"}}\n"
+        );
+        assert_eq!(
+            to_string(Event::Match {
+                path: "a.txt",
+                line: line("xyx"),
+                submatches: vec![
+                    Submatch {
+                        text: "x",
+                        start: 0,
+                        end: 1,
+                    },
+                    Submatch {
+                        text: "x",
+                        start: 2,
+                        end: 3,
+                    },
+                ],
+            }),
+            concat!(
+                r#"{"type":"match","data":{"path":"a.txt","line_number":2,"absolute_offset":4,"#,
+                r#""text":"xyx","submatches":[{"match":"x","start":0,"end":1},"#,
+                r#"{"match":"x","start":2,"end":3}]}}"#,
+                "\n"
+            )
+        );
+        assert_eq!(
+            to_string(Event::Context {
+                path: "a.txt",
+                line: line("y"),
+            }),
+            concat!(
+                r#"{"type":"context","data":{"path":"a.txt","line_number":2,"absolute_offset":4,"#,
+                r#""text":"y"}}"#,
+                "\n"
+            )
+        );
+        assert_eq!(
+            to_string(Event::End {
+                path: "a.txt",
+                stats: Stats {
+                    matched_lines: 1,
+                    matches: 2,
+                },
+            }),
+            concat!(
+                r#"{"type":"end","data":{"path":"a.txt","#,
+                r#""stats":{"matched_lines":1,"matches":2}}}"#,
+                "\n"
+            )
+        );
+    }
+}
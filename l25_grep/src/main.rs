@@ -1,22 +1,36 @@
 mod cli;
+mod json;
+mod pool;
 
 use std::{
     borrow::Cow,
     collections::VecDeque,
     error::Error,
     fs,
-    io::{self, BufRead},
+    io::{self, BufRead, Write},
     path::Path,
+    process::ExitCode,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
 };
 
 use clap::Parser;
-use cli::Cli;
+use cli::{Cli, Sort};
 use regex::Regex;
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() -> ExitCode {
+    run().unwrap_or_else(|err| {
+        eprintln!("l25_grep: {}", err);
+        ExitCode::from(2)
+    })
+}
+
+/// Run grep and return exit code:
+/// 0 - some line is selected, 1 - none, 2 - an error occurred.
+fn run() -> Result<ExitCode, Box<dyn Error>> {
     let Cli {
         pattern,
-        file,
+        mut files,
         after_context,
         before_context,
         context,
@@ -25,79 +39,308 @@ fn main() -> Result<(), Box<dyn Error>> {
         invert_match,
         fixed_string,
         line_number,
+        max_count,
+        quiet,
+        json,
+        threads,
+        sort,
     } = Cli::parse();
 
-    let context = if context > 0 {
+    let context = if quiet {
+        // nothing is printed, so context is of no use
+        (0, 0)
+    } else if context > 0 {
         (context, context)
     } else {
         (before_context, after_context)
     };
 
-    let total = for_each_match(
-        AppRegex::build(pattern, ignore_case, fixed_string, invert_match, context)?,
-        file,
-        |(id, line)| {
-            if count {
-                return;
+    let opts = Opts {
+        // the first match is enough to know the answer
+        max_count: if quiet { Some(1) } else { max_count },
+        count,
+        quiet,
+        json,
+        line_number,
+        with_filename: files.len() > 1,
+    };
+
+    let threads = match threads {
+        Some(n) => n as usize,
+        None => thread::available_parallelism().map_or(1, usize::from),
+    };
+
+    if sort == Sort::Path {
+        files.sort();
+    }
+
+    // one regex is shared by all workers
+    let re = AppRegex::build(pattern, ignore_case, fixed_string, invert_match, context)?;
+
+    let stop = AtomicBool::new(false);
+    let mut selected = false;
+    let mut failed = false;
+
+    let mut report = |file: &Path, res: Result<usize, io::Error>| match res {
+        Ok(total) if total > 0 => {
+            selected = true;
+
+            if quiet {
+                stop.store(true, Ordering::Relaxed);
             }
+        }
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("l25_grep: {}: {}", file.display(), err);
+            failed = true;
+        }
+    };
+
+    if threads <= 1 || files.len() <= 1 {
+        // nothing runs at the same time, so lines are written as soon as found
+        let mut out = io::stdout().lock();
 
-            if line_number {
-                println!("{}: {}", id, line);
-            } else {
-                println!("{}", line);
+        for file in &files {
+            // answer is already known
+            if stop.load(Ordering::Relaxed) {
+                break;
             }
-        },
-    )?;
 
-    if count {
-        println!("{}", total);
+            match search(&re, file, &opts, &mut out) {
+                // reader is gone, nobody needs the rest
+                Err(err) if err.kind() == io::ErrorKind::BrokenPipe => return Err(err.into()),
+                res => report(file, res),
+            }
+        }
+    } else {
+        pool::for_each_path(
+            &files,
+            threads,
+            sort == Sort::Path,
+            &stop,
+            // collect output of file to print it at once
+            |file| {
+                let mut out = Vec::new();
+                let res = search(&re, file, &opts, &mut out);
+                (res, out)
+            },
+            |file, (res, out)| {
+                // answer is already known
+                if stop.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+
+                io::stdout().lock().write_all(&out)?;
+                report(file, res);
+
+                Ok(())
+            },
+        )?;
     }
 
-    Ok(())
+    let code = match (selected, failed) {
+        (true, _) if quiet => 0,
+        (_, true) => 2,
+        (true, _) => 0,
+        (false, _) => 1,
+    };
+
+    Ok(ExitCode::from(code))
 }
 
+/// Options of printing found lines.
+struct Opts {
+    max_count: Option<usize>,
+    count: bool,
+    quiet: bool,
+    json: bool,
+    line_number: bool,
+    with_filename: bool,
+}
+
+/// Search `file` and write formatted result into `out`.
+/// Return number of exact matches.
+fn search(
+    re: &AppRegex,
+    file: &Path,
+    opts: &Opts,
+    out: &mut impl Write,
+) -> Result<usize, io::Error> {
+    let path = file.to_string_lossy();
+    let mut stats = json::Stats::default();
+    // open before anything is written, so unreadable file leaves no events
+    let reader = io::BufReader::new(fs::File::open(file)?);
+
+    if opts.json {
+        json::Event::Begin { path: &path }.write(out)?;
+    }
+
+    let total = for_each_match(re, reader, opts.max_count, |(state, id, line)| {
+        if opts.count || opts.quiet {
+            return Ok(());
+        }
+
+        if opts.json {
+            let text = line.text.as_str();
+            let line = json::Line {
+                line_number: id + 1,
+                absolute_offset: line.offset,
+                text,
+            };
+
+            return match state {
+                Match::Exact => {
+                    let submatches = re
+                        .spans(text)
+                        .map(|(start, end)| json::Submatch {
+                            text: &text[start..end],
+                            start,
+                            end,
+                        })
+                        .collect::<Vec<_>>();
+
+                    stats.matched_lines += 1;
+                    stats.matches += submatches.len();
+
+                    json::Event::Match {
+                        path: &path,
+                        line,
+                        submatches,
+                    }
+                    .write(out)
+                }
+                Match::Aside => json::Event::Context { path: &path, line }.write(out),
+                // never leaves buffer
+                Match::Skip => unreachable!(),
+            };
+        }
+
+        if opts.with_filename {
+            write!(out, "{}:", path)?;
+        }
+
+        if opts.line_number {
+            // counted from 1 like in JSON
+            writeln!(out, "{}: {}", id + 1, line.text)
+        } else {
+            writeln!(out, "{}", line.text)
+        }
+    })?;
+
+    if opts.json {
+        json::Event::End { path: &path, stats }.write(out)?;
+    }
+
+    if opts.count && !opts.quiet {
+        if opts.with_filename {
+            write!(out, "{}:", path)?;
+        }
+
+        writeln!(out, "{}", total)?;
+    }
+
+    Ok(total)
+}
+
+/// Call `f` for each line to be printed and return number of exact matches.
+/// Lines are classified by [`Match`], it's never [`Match::Skip`].
+///
+/// Stop reading once `max_count` exact matches are found,
+/// but still yield lines of trailing context.
 fn for_each_match(
-    re: AppRegex,
-    file: impl AsRef<Path>,
-    mut f: impl FnMut((usize, String)),
+    re: &AppRegex,
+    mut reader: impl BufRead,
+    max_count: Option<usize>,
+    mut f: impl FnMut((Match, usize, Line)) -> Result<(), io::Error>,
 ) -> Result<usize, io::Error> {
-    let reader = &mut {
-        let file = fs::File::open(file)?;
-        io::BufReader::new(file)
-    };
+    // nothing to look for
+    if max_count == Some(0) {
+        return Ok(0);
+    }
 
     // exact matches
     let mut count = 0;
     // all we need in one place
     let mut handle_match = |(state, id, line)| {
-        f((id, line));
-
         if let Match::Exact = state {
             count += 1;
         }
+
+        f((state, id, line))
     };
     // keep matches and update state automatically
     let mut mtchs = Matches::new(re.before, re.after);
+    // exact matches seen so far, they may still be in buffer
+    let mut found = 0;
+    // lines of trailing context left to read once limit is reached
+    let mut trailing = None;
+
+    // offset of the next line from start of file
+    let mut offset = 0;
 
     // handle each line of file
-    for (id, try_line) in reader.lines().enumerate() {
-        let line = try_line?;
-        let is_match = re.is_match(&line);
+    for id in 0.. {
+        if trailing == Some(0) {
+            break;
+        }
+
+        let mut text = String::new();
+        let len = reader.read_line(&mut text)?;
+
+        if len == 0 {
+            break;
+        }
+
+        // strip line ending the same way as `BufRead::lines` does
+        if text.ends_with('\n') {
+            text.pop();
+
+            if text.ends_with('\r') {
+                text.pop();
+            }
+        }
+
+        let line = Line { offset, text };
+        offset += len;
+
+        let is_match = match trailing.as_mut() {
+            // limit is reached, the rest is context only
+            Some(left) => {
+                *left -= 1;
+                false
+            }
+            None => re.is_match(&line.text),
+        };
+
+        if is_match {
+            found += 1;
+
+            if max_count.is_some_and(|max| found >= max) {
+                trailing = Some(re.after);
+            }
+        }
 
         // handle match which left buffer
         if let Some(val) = mtchs.insert(is_match, id, line) {
-            handle_match(val);
+            handle_match(val)?;
         }
     }
 
     // don't forget to handle once done with file
-    mtchs.clear().into_iter().for_each(handle_match);
+    mtchs.clear().into_iter().try_for_each(handle_match)?;
 
     Ok(count)
 }
 
+/// Line of file without line ending.
+struct Line {
+    /// offset of the first byte from start of file
+    offset: usize,
+    text: String,
+}
+
 struct Matches {
-    buf: VecDeque<(Match, usize, String)>,
+    buf: VecDeque<(Match, usize, Line)>,
     before: usize,
     after: usize,
     exacts: VecDeque<usize>,
@@ -116,7 +359,7 @@ impl Matches {
         }
     }
 
-    fn insert(&mut self, is_match: bool, id: usize, val: String) -> Option<(Match, usize, String)> {
+    fn insert(&mut self, is_match: bool, id: usize, val: Line) -> Option<(Match, usize, Line)> {
         let buf = &mut self.buf;
 
         // if it's less, then just fill out buffer
@@ -145,7 +388,7 @@ impl Matches {
         res
     }
 
-    fn clear(&mut self) -> impl IntoIterator<Item = (Match, usize, String)> + '_ {
+    fn clear(&mut self) -> impl IntoIterator<Item = (Match, usize, Line)> + '_ {
         self.buf
             .drain(..)
             .filter(|(state, ..)| !matches!(state, Match::Skip))
@@ -157,37 +400,30 @@ impl Matches {
 
         match buf[last] {
             (Match::Aside | Match::Skip, ..) => {
-                'out: loop {
-                    for &exact_id in &self.exacts {
-                        let (_, first_buf_id, _) = buf[0];
-
-                        if exact_id + self.after < first_buf_id {
-                            self.exacts.pop_front();
-                            continue 'out;
-                        }
-
-                        // fir
-                        // [..  ex + af
-
-                        // fir
-                        // [..  ex .. ex + af .. last
-
-                        let (left, right) = if exact_id <= first_buf_id {
-                            (0, exact_id + self.after - first_buf_id + 1)
-                        } else {
-                            let left = exact_id - first_buf_id;
-                            (left, buf.len().min(left + self.after))
-                        };
-
-                        (left..right).for_each(|id| {
-                            if let (Match::Skip, ..) = buf[id] {
-                                let (state, ..) = &mut buf[id];
-                                *state = Match::Aside;
-                            }
-                        });
+                // ids of buffered lines go in a row
+                let (_, first_buf_id, _) = buf[0];
+
+                // trailing context of these has already left buffer
+                while self
+                    .exacts
+                    .front()
+                    .is_some_and(|&exact_id| exact_id + self.after < first_buf_id)
+                {
+                    self.exacts.pop_front();
+                }
+
+                for (pos, (state, ..)) in buf.iter_mut().enumerate() {
+                    let id = first_buf_id + pos;
+                    let is_after = self
+                        .exacts
+                        .iter()
+                        .any(|&exact_id| exact_id < id && id <= exact_id + self.after);
+
+                    if is_after && matches!(state, Match::Skip) {
+                        *state = Match::Aside;
                     }
-                    break;
                 }
+
                 return;
             }
             (_, id, _) => self.exacts.push_back(id),
@@ -250,6 +486,15 @@ impl AppRegex {
     fn is_match(&self, haystack: &str) -> bool {
         self.inner.is_match(haystack) ^ self.invert_match
     }
+
+    /// Byte ranges of pattern occurrences in `haystack`.
+    /// There are none for inverted match.
+    fn spans<'a>(&'a self, haystack: &'a str) -> impl Iterator<Item = (usize, usize)> + 'a {
+        self.inner
+            .find_iter(haystack)
+            .filter(|_| !self.invert_match)
+            .map(|mtch| (mtch.start(), mtch.end()))
+    }
 }
 
 #[derive(Debug)]
@@ -268,3 +513,114 @@ impl From<bool> for Match {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        env,
+        io::{Cursor, Read},
+        process,
+    };
+
+    /// Reader which fails if it's read, to check that search stops in time.
+    struct Unreadable;
+
+    impl Read for Unreadable {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("read past the end"))
+        }
+    }
+
+    fn regex(pattern: &str, context: (u32, u32)) -> AppRegex {
+        AppRegex::build(pattern, false, false, false, context).unwrap()
+    }
+
+    /// Lines passed by `for_each_match` as `(is exact, line number, text)`.
+    fn lines(
+        re: &AppRegex,
+        text: &str,
+        max_count: Option<usize>,
+    ) -> (usize, Vec<(bool, usize, String)>) {
+        let mut lines = Vec::new();
+        let count = for_each_match(re, Cursor::new(text), max_count, |(state, id, line)| {
+            lines.push((matches!(state, Match::Exact), id, line.text));
+            Ok(())
+        })
+        .unwrap();
+
+        (count, lines)
+    }
+
+    #[test]
+    fn max_count() {
+        let re = regex("a", (0, 0));
+        let (count, found) = lines(&re, "a1\nb\na2\na3\n", Some(2));
+
+        assert_eq!(count, 2);
+        assert_eq!(
+            found,
+            [(true, 0, "a1".to_owned()), (true, 2, "a2".to_owned())]
+        );
+        assert_eq!(lines(&re, "a\n", Some(0)), (0, vec![]));
+    }
+
+    #[test]
+    fn max_count_with_context() {
+        let re = regex("a", (0, 2));
+        let (count, found) = lines(&re, "a1\nb1\nb2\nb3\na2\n", Some(1));
+
+        // trailing context is still printed, but the later match is not looked for
+        assert_eq!(count, 1);
+        assert_eq!(
+            found,
+            [
+                (true, 0, "a1".to_owned()),
+                (false, 1, "b1".to_owned()),
+                (false, 2, "b2".to_owned()),
+            ]
+        );
+
+        // lines after context are never taken for matches
+        let (count, found) = lines(&re, "a1\na2\na3\na4\n", Some(1));
+        assert_eq!(count, 1);
+        assert!(found.iter().skip(1).all(|(exact, ..)| !exact));
+    }
+
+    #[test]
+    fn stop_at_first_match() {
+        // quiet mode looks for one match without context
+        let re = regex("a", (0, 0));
+        let reader = io::BufReader::new(Cursor::new("b\na\n").chain(Unreadable));
+        let count = for_each_match(&re, reader, Some(1), |_| Ok(())).unwrap();
+
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn count_and_quiet() {
+        let re = regex("a", (0, 0));
+        let path = env::temp_dir().join(format!("l25_grep_{}_quiet.txt", process::id()));
+        fs::write(&path, "a\nb\na\n").unwrap();
+
+        let mut opts = Opts {
+            max_count: None,
+            count: true,
+            quiet: false,
+            json: false,
+            line_number: false,
+            with_filename: false,
+        };
+        let mut out = Vec::new();
+        assert_eq!(search(&re, &path, &opts, &mut out).unwrap(), 2);
+        assert_eq!(out, b"2\n");
+
+        opts.quiet = true;
+        opts.max_count = Some(1);
+        out.clear();
+        assert_eq!(search(&re, &path, &opts, &mut out).unwrap(), 1);
+        assert!(out.is_empty());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::AtomicBool,
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+/// Run `work` for each of `paths` on `threads` workers
+/// and pass results to `sink` on current thread.
+///
+/// If `ordered` is set, results come in the same order as `paths`,
+/// otherwise as soon as they're ready.
+/// Once `stop` is set, workers don't take new paths.
+pub fn for_each_path<T, W, S>(
+    paths: &[PathBuf],
+    threads: usize,
+    ordered: bool,
+    stop: &AtomicBool,
+    work: W,
+    mut sink: S,
+) -> Result<(), io::Error>
+where
+    T: Send,
+    W: Fn(&Path) -> T + Sync,
+    S: FnMut(&Path, T) -> Result<(), io::Error>,
+{
+    // workers take paths one by one by position
+    let next = &AtomicUsize::new(0);
+    let work = &work;
+
+    thread::scope(|s| {
+        let (tx, rx) = mpsc::channel();
+
+        for _ in 0..threads.min(paths.len()) {
+            let tx = tx.clone();
+
+            s.spawn(move || loop {
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                let pos = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = paths.get(pos) else {
+                    break;
+                };
+
+                // receiver is gone only if sink failed
+                if tx.send((pos, work(path))).is_err() {
+                    break;
+                }
+            });
+        }
+
+        // workers own the rest of Senders
+        drop(tx);
+
+        // results which came before their turn
+        let mut pending = BTreeMap::new();
+        // position of the next path to be passed to sink
+        let mut expected = 0;
+
+        for (pos, res) in rx {
+            if !ordered {
+                sink(&paths[pos], res)?;
+                continue;
+            }
+
+            pending.insert(pos, res);
+
+            while let Some(res) = pending.remove(&expected) {
+                sink(&paths[expected], res)?;
+                expected += 1;
+            }
+        }
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn paths(n: usize) -> Vec<PathBuf> {
+        (0..n).map(|i| PathBuf::from(format!("{:02}", i))).collect()
+    }
+
+    /// The earlier path, the longer its work takes.
+    fn slow(path: &Path) -> String {
+        let pos: u64 = path.to_str().unwrap().parse().unwrap();
+        thread::sleep(Duration::from_millis(5 * (10 - pos % 10)));
+        path.display().to_string()
+    }
+
+    #[test]
+    fn ordered() {
+        let paths = paths(20);
+        let mut done = Vec::new();
+
+        for_each_path(
+            &paths,
+            4,
+            true,
+            &AtomicBool::new(false),
+            slow,
+            |path, res| {
+                assert_eq!(path.to_str(), Some(res.as_str()));
+                done.push(res);
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            done,
+            Vec::from_iter(paths.iter().map(|path| path.display().to_string()))
+        );
+    }
+
+    #[test]
+    fn unordered() {
+        let paths = paths(20);
+        let mut done = Vec::new();
+
+        for_each_path(&paths, 4, false, &AtomicBool::new(false), slow, |_, res| {
+            done.push(res);
+            Ok(())
+        })
+        .unwrap();
+
+        // every path is passed once
+        done.sort();
+        assert_eq!(
+            done,
+            Vec::from_iter(paths.iter().map(|path| path.display().to_string()))
+        );
+    }
+
+    #[test]
+    fn stop() {
+        let paths = paths(20);
+        let stop = AtomicBool::new(false);
+        let mut done = 0;
+
+        for_each_path(&paths, 2, true, &stop, slow, |_, _| {
+            done += 1;
+            stop.store(true, Ordering::Relaxed);
+            Ok(())
+        })
+        .unwrap();
+
+        // workers take no new paths once stopped
+        assert!(done < paths.len());
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    fs,
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+/// Write `text` into a file shared by tests and return its path.
+fn input(name: &str, text: &str) -> PathBuf {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("l25_grep_{}", name));
+    fs::write(&path, text).unwrap();
+    path
+}
+
+fn grep(args: &[&str], files: &[&PathBuf]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_l25_grep"))
+        .args(args)
+        .args(files)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> &str {
+    std::str::from_utf8(&output.stdout).unwrap()
+}
+
+#[test]
+fn exit_codes() {
+    let file = input("codes.txt", "one\ntwo\n");
+    let missing = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("l25_grep_missing.txt");
+
+    assert_eq!(grep(&["two"], &[&file]).status.code(), Some(0));
+    assert_eq!(grep(&["three"], &[&file]).status.code(), Some(1));
+    assert_eq!(grep(&["two"], &[&missing]).status.code(), Some(2));
+    // error wins over found lines unless it's quiet
+    assert_eq!(grep(&["two"], &[&file, &missing]).status.code(), Some(2));
+    assert_eq!(
+        grep(&["-q", "two"], &[&file, &missing]).status.code(),
+        Some(0)
+    );
+    assert_eq!(grep(&["("], &[&file]).status.code(), Some(2));
+}
+
+#[test]
+fn quiet() {
+    let file = input("quiet.txt", "one\ntwo\n");
+
+    let output = grep(&["-q", "o"], &[&file]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+
+    let output = grep(&["-q", "three"], &[&file]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn max_count() {
+    let file = input("max.txt", "a1\nb1\na2\nb2\na3\n");
+
+    assert_eq!(stdout(&grep(&["-m", "2", "a"], &[&file])), "a1\na2\n");
+    // trailing context is printed even if it would match
+    assert_eq!(
+        stdout(&grep(&["-m", "1", "-A", "2", "a"], &[&file])),
+        "a1\nb1\na2\n"
+    );
+    assert_eq!(stdout(&grep(&["-m", "2", "-c", "a"], &[&file])), "2\n");
+    assert_eq!(grep(&["-m", "0", "a"], &[&file]).status.code(), Some(1));
+}
+
+#[test]
+fn json() {
+    let file = input("json.txt", "one\nfoo foo\nbar\n");
+    let path = file.to_str().unwrap();
+
+    let output = grep(&["--json", "-A", "1", "foo"], &[&file]);
+    let expected = [
+        format!(r#"{{"type":"begin","data":{{"path":"{}"}}}}"#, path),
+        format!(
+            concat!(
+                r#"{{"type":"match","data":{{"path":"{}","line_number":2,"absolute_offset":4,"#,
+                r#""text":"foo foo","submatches":[{{"match":"foo","start":0,"end":3}},"#,
+                r#"{{"match":"foo","start":4,"end":7}}]}}}}"#
+            ),
+            path
+        ),
+        format!(
+            concat!(
+                r#"{{"type":"context","data":{{"path":"{}","line_number":3,"#,
+                r#""absolute_offset":12,"text":"bar"}}}}"#
+            ),
+            path
+        ),
+        format!(
+            concat!(
+                r#"{{"type":"end","data":{{"path":"{}","#,
+                r#""stats":{{"matched_lines":1,"matches":2}}}}}}"#
+            ),
+            path
+        ),
+    ];
+
+    assert_eq!(stdout(&output).lines().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn line_number() {
+    let file = input("number.txt", "one\ntwo\n");
+
+    // numbers are the same as in JSON
+    assert_eq!(stdout(&grep(&["-n", "two"], &[&file])), "2: two\n");
+}
+
+#[test]
+fn parallel_sorted() {
+    let files = Vec::from_iter((0..16).rev().map(|i| {
+        // the first files are the largest, so they are done last
+        let text = format!("{}\n", i).repeat((16 - i) * 2000);
+        input(&format!("many_{:02}.txt", i), &text)
+    }));
+    let files = Vec::from_iter(files.iter());
+
+    let output = grep(&["-j", "4", "--sort", "path", "-c", "^"], &files);
+    let expected = Vec::from_iter((0..16).map(|i| {
+        let path =
+            PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("l25_grep_many_{:02}.txt", i));
+        format!("{}:{}", path.display(), (16 - i) * 2000)
+    }));
+    assert_eq!(stdout(&output).lines().collect::<Vec<_>>(), expected);
+
+    // lines of each file go together
+    let output = grep(&["-j", "4", "--sort", "path", "^"], &files);
+    let mut names = stdout(&output)
+        .lines()
+        .map(|line| line.split_once(':').unwrap().0)
+        .collect::<Vec<_>>();
+    names.dedup();
+    assert_eq!(names.len(), 16);
+    assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+}
@@ -1,55 +1,197 @@
-pub use clap::Parser;
+pub use clap::{error::ErrorKind, CommandFactory, Parser};
 
 use std::{
     error::Error, 
     fmt::{self, Display, Formatter}, 
-    num::ParseIntError
+    num::ParseIntError,
+    path::PathBuf,
 };
 
+use clap::{builder::NonEmptyStringValueParser, ArgGroup};
+use regex::Regex;
+
 #[derive(Parser)]
+#[clap(group(ArgGroup::new("list").required(true).args(["bytes", "characters", "fields"])))]
 pub struct Cli {
-    /// выбрать поля (колонки)
+    /// входные файлы, `-` или ничего - stdin
+    pub files: Vec<PathBuf>,
+
+    /// выбрать байты
+    #[clap(
+        short, 
+        allow_hyphen_values = true,
+        value_delimiter = ',',
+        value_parser = range_parser,
+    )]
+    pub bytes: Vec<Range>,
+
+    /// выбрать символы
+    #[clap(
+        short, 
+        allow_hyphen_values = true,
+        value_delimiter = ',',
+        value_parser = range_parser,
+    )]
+    pub characters: Vec<Range>,
+
+    /// выбрать поля (колонки), в режиме CSV - также по имени из заголовка
     #[clap(
         short, 
-        num_args = 1..,
-        required = true, 
+        allow_hyphen_values = true,
         value_delimiter = ',',
         value_parser = field_parser,
     )]
-    pub fields: Vec<u32>,
+    pub fields: Vec<Field>,
 
-    /// использовать другой разделитель
-    #[clap(short, default_value_t = '\t')]
-    pub delimiter: char,
+    /// использовать другой разделитель (по умолчанию - TAB, в режиме CSV - запятая)
+    #[clap(
+        short, 
+        conflicts_with_all = ["bytes", "characters"],
+        value_parser = NonEmptyStringValueParser::new(),
+    )]
+    pub delimiter: Option<String>,
 
     /// только строки с разделителем
-    #[clap(short)]
+    #[clap(short, conflicts_with_all = ["bytes", "characters"])]
     pub separated: bool,
+
+    /// не разбивать многобайтовые символы (вместе с -b)
+    #[clap(short = 'n')]
+    pub no_split: bool,
+
+    /// выбрать все, кроме указанного
+    #[clap(long)]
+    pub complement: bool,
+
+    /// выводить в порядке перечисления, допуская повторы
+    #[clap(long, conflicts_with = "complement")]
+    pub ordered: bool,
+
+    /// разделитель для вывода полей (по умолчанию - входной)
+    #[clap(long, conflicts_with_all = ["bytes", "characters"])]
+    pub output_delimiter: Option<String>,
+
+    /// разбирать поля в кавычках по RFC 4180
+    #[clap(long, conflicts_with_all = ["bytes", "characters"])]
+    pub csv: bool,
+
+    /// разделитель - регулярное выражение, например `\s+`
+    #[clap(
+        long, 
+        conflicts_with_all = ["bytes", "characters", "csv", "delimiter"],
+        value_parser = Regex::new,
+    )]
+    pub regex_delimiter: Option<Regex>,
+
+    /// записи разделены NUL, а не переводом строки
+    #[clap(short, long)]
+    pub zero_terminated: bool,
+}
+
+/// Range of positions, both ends are included and start with 0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Range {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Range {
+    pub fn contains(&self, pos: usize) -> bool {
+        (self.start..=self.end).contains(&pos)
+    }
 }
 
+/// Item of fields LIST.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Field {
+    Range(Range),
+    /// name of column in header
+    Name(String),
+}
+
+/// Parse a range, or take a name if it doesn't look like one.
+fn field_parser(str: &str) -> Result<Field, ParseRangeError> {
+    if str.chars().all(|ch| ch.is_ascii_digit() || ch == '-') {
+        return range_parser(str).map(Field::Range);
+    }
 
-fn field_parser(str: &str) -> Result<u32, ParseFieldError> {
-    match str.parse().map_err(ParseFieldError::ParseIntError)? {
-        0 => Err(ParseFieldError::LessThanOne),
+    Ok(Field::Name(str.to_owned()))
+}
+
+/// Parse one item of LIST: `N`, `N-M`, `N-` or `-M`.
+fn range_parser(str: &str) -> Result<Range, ParseRangeError> {
+    let pos_parser = |str: &str| match str.parse().map_err(ParseRangeError::ParseIntError)? {
+        0 => Err(ParseRangeError::LessThanOne),
         value => Ok(value - 1)
+    };
+
+    let (start, end) = match str.split_once('-') {
+        None => {
+            let pos = pos_parser(str)?;
+            (pos, pos)
+        }
+        Some(("", "")) => return Err(ParseRangeError::NoEnds),
+        Some(("", end)) => (0, pos_parser(end)?),
+        Some((start, "")) => (pos_parser(start)?, usize::MAX),
+        Some((start, end)) => (pos_parser(start)?, pos_parser(end)?),
+    };
+
+    if start > end {
+        return Err(ParseRangeError::Decreasing);
     }
+
+    Ok(Range { start, end })
 }
 
 #[derive(Debug)]
-enum ParseFieldError {
+enum ParseRangeError {
     ParseIntError(ParseIntError),
     LessThanOne,
+    NoEnds,
+    Decreasing,
 }
 
-impl Display for ParseFieldError {
+impl Display for ParseRangeError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        use ParseFieldError::*;
+        use ParseRangeError::*;
 
         match self {
             ParseIntError(err) => write!(f, "{}", err),
             LessThanOne => write!(f, "value must be greater or equal to 1"),
+            NoEnds => write!(f, "range must have at least one end"),
+            Decreasing => write!(f, "range must not be decreasing"),
         }
     }
 }
 
-impl Error for ParseFieldError {}
+impl Error for ParseRangeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{field_parser, range_parser, Field, Range};
+
+    #[test]
+    fn single() {
+        assert_eq!(range_parser("3").unwrap(), Range { start: 2, end: 2 });
+        assert!(range_parser("0").is_err());
+        assert!(range_parser("x").is_err());
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(range_parser("2-4").unwrap(), Range { start: 1, end: 3 });
+        assert_eq!(range_parser("-3").unwrap(), Range { start: 0, end: 2 });
+        let open = Range { start: 4, end: usize::MAX };
+        assert_eq!(range_parser("5-").unwrap(), open);
+        assert!(range_parser("-").is_err());
+        assert!(range_parser("4-2").is_err());
+    }
+
+    #[test]
+    fn names() {
+        let name = Field::Name("email".to_owned());
+        assert_eq!(field_parser("email").unwrap(), name);
+        assert!(matches!(field_parser("2-3").unwrap(), Field::Range(_)));
+        assert!(field_parser("0").is_err());
+    }
+}
@@ -0,0 +1,158 @@
+//! Splitting and joining records according to RFC 4180.
+//!
+//! A field may be wrapped into double quotes,
+//! then it can contain delimiter, line breaks and escaped quotes (`""`).
+
+use std::borrow::Cow;
+
+const QUOTE: char = '"';
+
+/// Check if `record` has no unclosed quoted field, so it doesn't go on with the next line.
+///
+/// Quotes are followed the way [`split`] does, so a quote inside unquoted field is a literal.
+pub fn is_complete(record: &str, del: &str) -> bool {
+    // start of current field in record
+    let mut start = 0;
+    let mut in_quotes = false;
+    let mut chars = record.char_indices().peekable();
+
+    while let Some((pos, ch)) = chars.next() {
+        if in_quotes {
+            match ch {
+                // "" is an escaped quote
+                QUOTE if chars.next_if(|&(_, ch)| ch == QUOTE).is_some() => {}
+                QUOTE => in_quotes = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        // delimiter might be longer than one char
+        if pos >= start && record[pos..].starts_with(del) {
+            start = pos + del.len();
+            continue;
+        }
+
+        // quote opens field only at its very beginning
+        if ch == QUOTE && pos == start {
+            in_quotes = true;
+        }
+    }
+
+    !in_quotes
+}
+
+/// Split `record` into unquoted fields.
+pub fn split<'a>(record: &'a str, del: &str) -> Vec<Cow<'a, str>> {
+    let mut flds = Vec::new();
+    // start of current field in record
+    let mut start = 0;
+    // unescaped value if field is quoted
+    let mut quoted: Option<String> = None;
+    let mut in_quotes = false;
+    let mut chars = record.char_indices().peekable();
+
+    while let Some((pos, ch)) = chars.next() {
+        // delimiter might be longer than one char
+        if !in_quotes && pos >= start && record[pos..].starts_with(del) {
+            flds.push(take_field(&record[start..pos], quoted.take()));
+            start = pos + del.len();
+            continue;
+        }
+
+        // rest of delimiter
+        if pos < start {
+            continue;
+        }
+
+        if in_quotes {
+            match ch {
+                // "" is an escaped quote
+                QUOTE if chars.next_if(|&(_, ch)| ch == QUOTE).is_some() => {
+                    quoted.get_or_insert_with(String::new).push(QUOTE)
+                }
+                QUOTE => in_quotes = false,
+                _ => quoted.get_or_insert_with(String::new).push(ch),
+            }
+            continue;
+        }
+
+        match ch {
+            // quote opens field only at its very beginning
+            QUOTE if pos == start && quoted.is_none() => {
+                quoted = Some(String::new());
+                in_quotes = true;
+            }
+            // text after closing quote is kept as is
+            _ => {
+                if let Some(val) = quoted.as_mut() {
+                    val.push(ch);
+                }
+            }
+        }
+    }
+
+    flds.push(take_field(&record[start..], quoted));
+
+    flds
+}
+
+fn take_field(raw: &str, quoted: Option<String>) -> Cow<'_, str> {
+    match quoted {
+        Some(val) => val.into(),
+        None => raw.into(),
+    }
+}
+
+/// Wrap `fld` into quotes if it can't be written as is.
+pub fn quote<'a>(fld: &'a str, del: &str) -> Cow<'a, str> {
+    let must_quote =
+        fld.contains(QUOTE) || fld.contains(['\n', '\r']) || (!del.is_empty() && fld.contains(del));
+
+    if !must_quote {
+        return fld.into();
+    }
+
+    format!("{0}{1}{0}", QUOTE, fld.replace(QUOTE, r#""""#)).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain() {
+        assert_eq!(split("a,b,,c", ","), ["a", "b", "", "c"]);
+        assert_eq!(split("", ","), [""]);
+        assert_eq!(split("a::b:c", "::"), ["a", "b:c"]);
+    }
+
+    #[test]
+    fn quoted() {
+        assert_eq!(split(r#""a,b",c"#, ","), ["a,b", "c"]);
+        assert_eq!(split(r#""say ""hi""",x"#, ","), [r#"say "hi""#, "x"]);
+        assert_eq!(split("\"multi\nline\",z", ","), ["multi\nline", "z"]);
+        assert_eq!(split(r#"a"b,c"#, ","), [r#"a"b"#, "c"]);
+    }
+
+    #[test]
+    fn complete() {
+        assert!(is_complete(r#""a ""b""",c"#, ","));
+        assert!(!is_complete(r#""a,b"#, ","));
+        assert!(!is_complete(r#"x::"a"#, "::"));
+    }
+
+    #[test]
+    fn literal_quote() {
+        // quote in the middle of unquoted field doesn't open anything
+        assert!(is_complete(r#"a"b,c"#, ","));
+        assert_eq!(split(r#"a"b,c"#, ","), [r#"a"b"#, "c"]);
+    }
+
+    #[test]
+    fn requote() {
+        assert_eq!(quote("plain", ","), "plain");
+        assert_eq!(quote("a,b", ","), r#""a,b""#);
+        assert_eq!(quote(r#"say "hi""#, "\t"), r#""say ""hi""""#);
+    }
+}
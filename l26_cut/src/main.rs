@@ -1,94 +1,472 @@
 mod cli;
+mod csv;
 
-use std::{borrow::Cow, collections::HashSet, io, mem};
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    mem,
+    path::PathBuf,
+    process,
+};
 
-use cli::{Cli, Parser};
+use cli::{Cli, CommandFactory, ErrorKind, Field, Parser, Range};
+use regex::Regex;
 
 fn main() {
     // parse and prepare arguments
-    let (flds, del, sep) = parse_args();
+    let (mut select, del, opts, files) = parse_args();
 
+    // write all lines into one locked handle
+    let mut out = io::stdout().lock();
+    // report all bad inputs, but don't stop on them
+    let mut failed = false;
+
+    for path in files {
+        let res = if path.as_os_str() == "-" {
+            cut_input(io::stdin().lock(), &mut select, &del, &opts, &mut out)
+        } else {
+            File::open(&path).map_err(CutError::from).and_then(|file| {
+                cut_input(BufReader::new(file), &mut select, &del, &opts, &mut out)
+            })
+        };
+
+        match res {
+            Ok(()) => {}
+            Err(CutError::Io(err)) => {
+                eprintln!("l26_cut: {}: {}", path.display(), err);
+                failed = true;
+            }
+            // LIST is wrong for every input, so there is no point to go on
+            Err(CutError::NoColumn(name)) => {
+                eprintln!("l26_cut: no column named {}", name);
+                process::exit(1);
+            }
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// Failure of cutting an input.
+enum CutError {
+    /// reading or writing failed, other inputs still can be processed
+    Io(io::Error),
+    /// name of LIST is missing in CSV header
+    NoColumn(String),
+}
+
+impl From<io::Error> for CutError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Options of reading and writing records.
+struct Opts {
+    /// joins selected fields
+    out_del: String,
+    /// skip lines without delimiter
+    sep: bool,
+    /// end of each record, both input and output
+    term: u8,
+}
+
+/// Read and process each record of `reader` on the fly
+/// until no input or an error occurrs.
+fn cut_input(
+    mut reader: impl BufRead,
+    select: &mut Select,
+    del: &Delimiter,
+    opts: &Opts,
+    out: &mut impl Write,
+) -> Result<(), CutError> {
     // buffer to read into
-    let mut in_buf = String::new();
-
-    // read and process each line on the fly
-    // until no input or an error occurrs
-    while 0 < io::stdin()
-        .read_line(&mut in_buf)
-        .expect("failed to read line")
-    {
+    let mut in_buf = Vec::new();
+    // CSV record might take several lines
+    let mut record = String::new();
+
+    // each input starts with its own CSV header
+    let mut is_first = true;
+
+    let mut write_record = |line: &[u8], select: &mut Select| -> Result<(), CutError> {
+        let is_header = mem::replace(&mut is_first, false);
+
+        // print out result, selected bytes might be not valid UTF-8
+        if let Some(res) = cut_record(line, is_header, select, del, opts)? {
+            out.write_all(&res)?;
+            out.write_all(&[opts.term])?;
+        }
+
+        Ok(())
+    };
+
+    while 0 < reader.read_until(opts.term, &mut in_buf)? {
         // get line and clear buffer
-        let buf = in_buf.drain(..);
-        // \n is de trop in the end
-        let line = buf.as_str().trim_end();
+        let buf = mem::take(&mut in_buf);
+
+        if let Select::Csv(..) = select {
+            // line ending stays within quoted field as is
+            record.push_str(decode(&buf)?);
 
-        // maybe yield some matched columns
-        let mb_cols = cut(line, &flds, del);
+            // quoted field goes on with the next line
+            if !csv::is_complete(&record, csv_del(del)) {
+                continue;
+            }
 
-        // skip if no matches but _separated_
-        if mb_cols.is_none() && sep {
+            write_record(trim_term(record.as_bytes(), opts.term), select)?;
+            record.clear();
             continue;
         }
 
-        // print out formatted result string
-        println!("{}", format_with_or(line, mb_cols, del));
+        write_record(trim_term(&buf, opts.term), select)?;
+    }
+
+    // quote is never closed, so take the rest as is
+    if !record.is_empty() {
+        write_record(trim_term(record.as_bytes(), opts.term), select)?;
+    }
+
+    Ok(())
+}
+
+/// Strip terminator of record, which is de trop in the end.
+fn trim_term(buf: &[u8], term: u8) -> &[u8] {
+    match term {
+        b'\n' => trim_end(buf, b"\n\r"),
+        term => trim_end(buf, &[term]),
+    }
+}
+
+/// Strip all `bytes` from the end of `buf`.
+fn trim_end<'a>(buf: &'a [u8], bytes: &[u8]) -> &'a [u8] {
+    let len = buf
+        .iter()
+        .rposition(|byte| !bytes.contains(byte))
+        .map_or(0, |pos| pos + 1);
+
+    &buf[..len]
+}
+
+/// Take `line` as UTF-8 text, which all modes but bytes require.
+fn decode(line: &[u8]) -> Result<&str, CutError> {
+    std::str::from_utf8(line).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err).into())
+}
+
+/// Select parts of `line`, or return None if it must be skipped.
+fn cut_record<'a>(
+    line: &'a [u8],
+    is_header: bool,
+    select: &mut Select,
+    del: &Delimiter,
+    opts: &Opts,
+) -> Result<Option<Cow<'a, [u8]>>, CutError> {
+    let res = match select {
+        Select::Bytes(slctr, no_split) => cut_bytes(line, slctr, *no_split),
+        Select::Characters(slctr) => cut_chars(decode(line)?, slctr).into_bytes().into(),
+        Select::Fields(slctr) => {
+            let line = decode(line)?;
+            // maybe yield some matched columns
+            let mb_cols = cut(line, slctr, del);
+
+            // skip if no matches but _separated_
+            if mb_cols.is_none() && opts.sep {
+                return Ok(None);
+            }
+
+            // formatted result string
+            match format_with_or(line, mb_cols, &opts.out_del) {
+                Cow::Borrowed(str) => str.as_bytes().into(),
+                Cow::Owned(string) => string.into_bytes().into(),
+            }
+        }
+        Select::Csv(slctr, names) => {
+            let line = decode(line)?;
+            let flds = csv::split(line, csv_del(del));
+
+            // the first record is header with names of columns
+            if let (true, Some(fields)) = (is_header, names.as_ref()) {
+                slctr.rngs = resolve_names(fields.clone(), &flds)?;
+            }
+
+            // no delimiter - keep line as is, like plain fields do
+            if flds.len() == 1 {
+                if opts.sep {
+                    return Ok(None);
+                }
+
+                line.as_bytes().into()
+            } else {
+                let cols = slctr
+                    .positions(flds.len())
+                    .into_iter()
+                    .map(|pos| csv::quote(&flds[pos], &opts.out_del));
+
+                let mut fmtd = String::new();
+
+                for (id, col) in cols.enumerate() {
+                    if id > 0 {
+                        fmtd.push_str(&opts.out_del);
+                    }
+
+                    fmtd.push_str(&col);
+                }
+
+                fmtd.into_bytes().into()
+            }
+        }
+    };
+
+    Ok(Some(res))
+}
+
+/// Delimiter of CSV records, which is always a plain string.
+fn csv_del(del: &Delimiter) -> &str {
+    match del {
+        Delimiter::Str(del) => del,
+        Delimiter::Regex(_) => unreachable!("CSV conflicts with regex delimiter"),
+    }
+}
+
+/// Separator of fields.
+enum Delimiter {
+    Str(String),
+    Regex(Regex),
+}
+
+impl Delimiter {
+    fn split<'a>(&self, line: &'a str) -> Vec<&'a str> {
+        match self {
+            Self::Str(del) => line.split(del.as_str()).collect(),
+            Self::Regex(re) => re.split(line).collect(),
+        }
     }
 }
 
-fn parse_args() -> (HashSet<usize>, char, bool) {
+/// What to select from each line.
+enum Select {
+    /// byte ranges, and whether multibyte chars must be kept whole
+    Bytes(Selector, bool),
+    Characters(Selector),
+    Fields(Selector),
+    /// fields of RFC 4180 records, and LIST to be resolved against header of each input
+    Csv(Selector, Option<Vec<Field>>),
+}
+
+/// Ranges of LIST and the way to apply them.
+struct Selector {
+    rngs: Vec<Range>,
+    /// take everything but ranges
+    complement: bool,
+    /// follow ranges as listed, so positions can repeat
+    ordered: bool,
+}
+
+impl Selector {
+    /// Positions to be taken out of `len` items, in order of output.
+    fn positions(&self, len: usize) -> Vec<usize> {
+        if self.ordered {
+            return self
+                .rngs
+                .iter()
+                .flat_map(|rng| rng.start..=rng.end.min(len.saturating_sub(1)))
+                .filter(|&pos| pos < len)
+                .collect();
+        }
+
+        (0..len)
+            .filter(|&pos| self.rngs.iter().any(|rng| rng.contains(pos)) ^ self.complement)
+            .collect()
+    }
+}
+
+fn parse_args() -> (Select, Delimiter, Opts, Vec<PathBuf>) {
     let Cli {
+        bytes,
+        characters,
         fields,
         delimiter,
         separated,
+        no_split,
+        complement,
+        ordered,
+        output_delimiter,
+        csv,
+        regex_delimiter,
+        zero_terminated,
+        mut files,
     } = Cli::parse();
 
-    // keep only unique fields (columns)
-    let columns = fields.into_iter().map(|val| val as _).collect();
+    let selector = |rngs| Selector {
+        rngs,
+        complement,
+        ordered,
+    };
+
+    // exactly one of lists is provided
+    let select = if !bytes.is_empty() {
+        Select::Bytes(selector(bytes), no_split)
+    } else if !characters.is_empty() {
+        Select::Characters(selector(characters))
+    } else if csv {
+        // names can be resolved only once header is read
+        if fields.iter().any(|fld| matches!(fld, Field::Name(_))) {
+            Select::Csv(selector(Vec::new()), Some(fields))
+        } else {
+            Select::Csv(selector(ranges_of(fields)), None)
+        }
+    } else {
+        if fields.iter().any(|fld| matches!(fld, Field::Name(_))) {
+            Cli::command()
+                .error(ErrorKind::ValueValidation, "field names require --csv")
+                .exit();
+        }
+
+        Select::Fields(selector(ranges_of(fields)))
+    };
+
+    let delimiter = delimiter.unwrap_or_else(|| if csv { "," } else { "\t" }.to_owned());
+
+    // join fields with input delimiter by default,
+    // though regex can't be used for that
+    let (del, out_del) = match regex_delimiter {
+        Some(re) => (Delimiter::Regex(re), output_delimiter.unwrap_or(delimiter)),
+        None => {
+            let out_del = output_delimiter.unwrap_or_else(|| delimiter.clone());
+            (Delimiter::Str(delimiter), out_del)
+        }
+    };
+
+    let opts = Opts {
+        out_del,
+        sep: separated,
+        term: if zero_terminated { b'\0' } else { b'\n' },
+    };
+
+    // read stdin if nothing is given
+    if files.is_empty() {
+        files.push(PathBuf::from("-"));
+    }
+
+    (select, del, opts, files)
+}
+
+/// Take ranges of `fields` which are known to have no names.
+fn ranges_of(fields: Vec<Field>) -> Vec<Range> {
+    fields
+        .into_iter()
+        .filter_map(|fld| match fld {
+            Field::Range(rng) => Some(rng),
+            Field::Name(_) => None,
+        })
+        .collect()
+}
+
+/// Turn names of `fields` into positions of columns in `header`.
+///
+/// # Error
+/// Fail if there is no column with such name.
+fn resolve_names(fields: Vec<Field>, header: &[Cow<'_, str>]) -> Result<Vec<Range>, CutError> {
+    fields
+        .into_iter()
+        .map(|fld| match fld {
+            Field::Range(rng) => Ok(rng),
+            Field::Name(name) => {
+                let pos = header
+                    .iter()
+                    .position(|col| *col == name)
+                    .ok_or(CutError::NoColumn(name))?;
+
+                Ok(Range {
+                    start: pos,
+                    end: pos,
+                })
+            }
+        })
+        .collect()
+}
+
+/// Select bytes of `line` by their positions, `line` doesn't have to be UTF-8.
+/// If `no_split` is set, a multibyte char is taken whole when its first byte is selected.
+fn cut_bytes<'a>(line: &'a [u8], slctr: &Selector, no_split: bool) -> Cow<'a, [u8]> {
+    let positions = slctr.positions(line.len()).into_iter();
+
+    if no_split {
+        return positions
+            // rest of multibyte char goes with its first byte
+            .filter(|&pos| !is_continuation(line[pos]))
+            .flat_map(|pos| &line[pos..(pos + char_width(line[pos])).min(line.len())])
+            .copied()
+            .collect::<Vec<_>>()
+            .into();
+    }
+
+    positions.map(|pos| line[pos]).collect::<Vec<_>>().into()
+}
+
+/// Check if `byte` is not the first one of UTF-8 char.
+fn is_continuation(byte: u8) -> bool {
+    byte & 0b1100_0000 == 0b1000_0000
+}
 
-    (columns, delimiter, separated)
+/// Length of UTF-8 char starting with `byte`, invalid one is taken alone.
+fn char_width(byte: u8) -> usize {
+    match byte {
+        0b1100_0000..=0b1101_1111 => 2,
+        0b1110_0000..=0b1110_1111 => 3,
+        0b1111_0000..=0b1111_0111 => 4,
+        _ => 1,
+    }
 }
 
-/// Separate `line` by appropriate delimiter ([`del`]) and return columns according to fields ([`flds`]).
+/// Select chars (Unicode scalar values) of `line` by their positions.
+fn cut_chars(line: &str, slctr: &Selector) -> String {
+    let chars = line.chars().collect::<Vec<_>>();
+
+    slctr
+        .positions(chars.len())
+        .into_iter()
+        .map(|pos| chars[pos])
+        .collect()
+}
+
+/// Separate `line` by appropriate delimiter ([`del`]) and return columns according to selector ([`slctr`]).
 ///
 /// # Schema
-/// |------|-----|-----------|
-/// | -del |  _  | None      |
-/// | +del | +id | Some(col) |
-/// | +del | -id | None      |
-/// |------|-----|-----------|
+/// |------|-----------------------|
+/// | -del | None                  |
+/// | +del | Some(selected cols)   |
+/// |------|-----------------------|
 ///
 /// # Legend
 /// - ±`del` - line does (not) have delimiter,
-/// - ±`id` - fields do (not) contain column id,
-/// - 3rd column - value of item of iterator.
+/// - 2nd column - returned value.
 fn cut<'a>(
     line: &'a str,
-    flds: &'a HashSet<usize>,
-    del: char,
-) -> Option<impl Iterator<Item = Option<&'a str>>> {
+    slctr: &Selector,
+    del: &Delimiter,
+) -> Option<impl Iterator<Item = &'a str>> {
+    // get columns according to Schema
+    let cols = del.split(line);
+
     // no delimiter - no talk
-    if !line.contains(del) {
+    if cols.len() < 2 {
         return None;
     }
 
-    // get columns according to Schema
-    let cols = line
-        .split(del)
-        .enumerate()
-        .map(|(id, col)| match flds.contains(&id) {
-            true => Some(col),
-            false => None,
-        });
+    let selected = slctr.positions(cols.len());
 
-    Some(cols)
+    Some(selected.into_iter().map(move |pos| cols[pos]))
 }
 
 /// Build formatted string from iterator (`mb_cols`) over columns.
 /// When iterator is None, return owned default value (`dflt`).
 fn format_with_or<'a>(
     dflt: &'a str,
-    mb_cols: Option<impl Iterator<Item = Option<&'a str>>>,
-    del: char,
+    mb_cols: Option<impl Iterator<Item = &'a str>>,
+    del: &str,
 ) -> Cow<'a, str> {
     match mb_cols {
         None => dflt.into(),
@@ -99,11 +477,11 @@ fn format_with_or<'a>(
             let mut any = false;
 
             // join cols with del if any
-            for col in cols.flatten() {
+            for col in cols {
                 let buf = fmtd.to_mut();
 
                 if mem::replace(&mut any, true) {
-                    buf.push(del);
+                    buf.push_str(del);
                 }
 
                 buf.push_str(col);
@@ -113,3 +491,168 @@ fn format_with_or<'a>(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Range of 1-based positions like in LIST, `0` stands for an open end.
+    fn rng(start: usize, end: usize) -> Range {
+        Range {
+            start: start - 1,
+            end: end.checked_sub(1).unwrap_or(usize::MAX),
+        }
+    }
+
+    fn selector(rngs: Vec<Range>, complement: bool, ordered: bool) -> Selector {
+        Selector {
+            rngs,
+            complement,
+            ordered,
+        }
+    }
+
+    fn opts(out_del: &str) -> Opts {
+        Opts {
+            out_del: out_del.to_owned(),
+            sep: false,
+            term: b'\n',
+        }
+    }
+
+    /// Output of cutting `input` as a whole.
+    fn cut_all(input: &[u8], select: &mut Select, del: &Delimiter, opts: &Opts) -> Vec<u8> {
+        let mut out = Vec::new();
+        assert!(cut_input(input, select, del, opts, &mut out).is_ok());
+        out
+    }
+
+    #[test]
+    fn positions() {
+        let plain = selector(vec![rng(3, 3), rng(1, 1)], false, false);
+        assert_eq!(plain.positions(4), [0, 2]);
+
+        let open = selector(vec![rng(2, 0)], false, false);
+        assert_eq!(open.positions(4), [1, 2, 3]);
+        assert_eq!(open.positions(0), []);
+    }
+
+    #[test]
+    fn complement() {
+        let slctr = selector(vec![rng(2, 3), rng(5, 0)], true, false);
+        assert_eq!(slctr.positions(7), [0, 3]);
+
+        // everything is selected, so nothing is left
+        let slctr = selector(vec![rng(1, 0)], true, false);
+        assert_eq!(slctr.positions(3), []);
+    }
+
+    #[test]
+    fn ordered() {
+        // duplicates are kept
+        let slctr = selector(vec![rng(3, 3), rng(1, 2), rng(3, 3)], false, true);
+        assert_eq!(slctr.positions(4), [2, 0, 1, 2]);
+
+        // open range takes the rest, missing positions are skipped
+        let slctr = selector(vec![rng(3, 0), rng(1, 1), rng(9, 9)], false, true);
+        assert_eq!(slctr.positions(4), [2, 3, 0]);
+    }
+
+    #[test]
+    fn output_delimiter() {
+        let del = Delimiter::Str(",".to_owned());
+        let mut select = Select::Fields(selector(vec![rng(3, 3), rng(1, 1)], false, true));
+
+        assert_eq!(
+            cut_all(b"a,b,c\nno delimiter\n", &mut select, &del, &opts(" | ")),
+            b"c | a\nno delimiter\n"
+        );
+
+        let mut select = Select::Fields(selector(vec![rng(2, 2)], true, false));
+        assert_eq!(cut_all(b"a,b,c\n", &mut select, &del, &opts(";")), b"a;c\n");
+    }
+
+    #[test]
+    fn csv_header_of_each_input() {
+        let del = Delimiter::Str(",".to_owned());
+        let names = vec![Field::Name("name".to_owned())];
+        let mut select = Select::Csv(selector(Vec::new(), false, false), Some(names));
+
+        assert_eq!(
+            cut_all(b"id,name\n1,a\n", &mut select, &del, &opts(",")),
+            b"name\na\n"
+        );
+        assert_eq!(
+            cut_all(b"name,id\nb,2\n", &mut select, &del, &opts(",")),
+            b"name\nb\n"
+        );
+    }
+
+    #[test]
+    fn csv_line_ending_in_field() {
+        let del = Delimiter::Str(",".to_owned());
+        let mut select = Select::Csv(selector(vec![rng(2, 2)], false, false), None);
+
+        assert_eq!(
+            cut_all(b"1,\"a\r\nb\"\r\n2,c\r\n", &mut select, &del, &opts(",")),
+            b"\"a\r\nb\"\nc\n"
+        );
+    }
+
+    #[test]
+    fn string_delimiter() {
+        let del = Delimiter::Str("::".to_owned());
+        let mut select = Select::Fields(selector(vec![rng(2, 0)], false, false));
+
+        assert_eq!(
+            cut_all(b"a::b:c::d\na:b\n", &mut select, &del, &opts("::")),
+            b"b:c::d\na:b\n"
+        );
+    }
+
+    #[test]
+    fn regex_delimiter() {
+        let del = Delimiter::Regex(Regex::new(r"\s+").unwrap());
+        let mut select = Select::Fields(selector(vec![rng(1, 1), rng(3, 3)], false, false));
+
+        assert_eq!(
+            cut_all(b"a  b\t\tc d\n", &mut select, &del, &opts(",")),
+            b"a,c\n"
+        );
+    }
+
+    #[test]
+    fn zero_terminated() {
+        let del = Delimiter::Str(",".to_owned());
+        let mut select = Select::Fields(selector(vec![rng(2, 2)], false, false));
+        let opts = Opts {
+            term: b'\0',
+            ..opts(",")
+        };
+
+        // line breaks are just a part of record
+        assert_eq!(
+            cut_all(b"a,b\nc\0d,e\0", &mut select, &del, &opts),
+            b"b\nc\0e\0"
+        );
+        // the last record may go without terminator
+        assert_eq!(cut_all(b"a,b", &mut select, &del, &opts), b"b\0");
+    }
+
+    #[test]
+    fn bytes_of_any_input() {
+        let line = b"\xffa\xd0\xb6b";
+        let slctr = selector(vec![rng(1, 3)], false, false);
+
+        assert_eq!(cut_bytes(line, &slctr, false), &line[..3]);
+        // the first byte of 'ж' is selected, so the char is taken whole
+        assert_eq!(cut_bytes(line, &slctr, true), &line[..4]);
+
+        let mut select = Select::Bytes(selector(vec![rng(1, 1)], false, false), false);
+        let del = Delimiter::Str("\t".to_owned());
+        assert_eq!(
+            cut_all(b"\xfe\xff\n", &mut select, &del, &opts("\t")),
+            b"\xfe\n"
+        );
+    }
+}
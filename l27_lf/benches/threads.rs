@@ -0,0 +1,119 @@
+//! Compare time of counting on 1..N threads, where N is number of cores,
+//! and then time of the same counting on different runtimes.
+//!
+//! Run with `cargo bench -p l27_lf`, size of generated file (MiB)
+//! can be changed by `L27_BENCH_MIB` environment variable.
+
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    fs,
+    hint::black_box,
+    io::{self, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use l27_lf::{count_letters, merge_ascii, merge_map, nonblocking, pool, ASCII_ABC};
+
+const SAMPLE: &str = "The quick brown fox jumps over the lazy dog. \
+                      Съешь же ещё этих мягких французских булок, да выпей чаю.\n";
+
+/// Best of runs is taken to reduce noise.
+const RUNS: usize = 3;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mib = env::var("L27_BENCH_MIB")
+        .ok()
+        .and_then(|val| val.parse().ok())
+        .unwrap_or(64);
+
+    let path = env::temp_dir().join("l27_lf_bench.txt");
+
+    // fill file with sample text
+    {
+        let mut file = io::BufWriter::new(fs::File::create(&path)?);
+
+        for _ in 0..(mib << 20) / SAMPLE.len() {
+            file.write_all(SAMPLE.as_bytes())?;
+        }
+    }
+
+    let max = thread::available_parallelism().map_or(1, usize::from);
+
+    println!("file: {} MiB, best of {} runs", mib, RUNS);
+    println!(
+        "{:>7} {:>12} {:>8} {:>12} {:>8}",
+        "threads", "ascii", "x", "unicode", "x"
+    );
+
+    let mut base = None;
+
+    for thrds in 1..=max {
+        let ascii = best_of(|| {
+            let init = ASCII_ABC.map(|ch| (ch, 0));
+            count_letters(&path, thrds, init, merge_ascii).map(black_box)
+        })?;
+        let unicode =
+            best_of(|| count_letters(&path, thrds, HashMap::new(), merge_map).map(black_box))?;
+
+        let (ascii_1, unicode_1) = *base.get_or_insert((ascii, unicode));
+
+        println!(
+            "{:>7} {:>12.3?} {:>8.2} {:>12.3?} {:>8.2}",
+            thrds,
+            ascii,
+            ascii_1.as_secs_f64() / ascii.as_secs_f64(),
+            unicode,
+            unicode_1.as_secs_f64() / unicode.as_secs_f64(),
+        );
+    }
+
+    println!();
+    println!(
+        "{:>7} {:>12} {:>12} {:>12}",
+        "threads", "std", "rayon", "tokio"
+    );
+
+    for thrds in 1..=max {
+        let init = ASCII_ABC.map(|ch| (ch, 0));
+
+        let std = best_of(|| count_letters(&path, thrds, init, merge_ascii).map(black_box))?;
+
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(thrds).build()?;
+        let rayon =
+            best_of(|| pool::count_letters(&path, &pool, init, merge_ascii).map(black_box))?;
+
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(thrds)
+            .max_blocking_threads(thrds)
+            .build()?;
+        let tokio = best_of(|| {
+            rt.block_on(async {
+                let file = tokio::fs::File::open(&path).await?;
+                nonblocking::count_letters(file, thrds, init, merge_ascii).await
+            })
+            .map(black_box)
+        })?;
+
+        println!(
+            "{:>7} {:>12.3?} {:>12.3?} {:>12.3?}",
+            thrds, std, rayon, tokio
+        );
+    }
+
+    Ok(fs::remove_file(path)?)
+}
+
+fn best_of<T>(mut f: impl FnMut() -> Result<T, io::Error>) -> Result<Duration, io::Error> {
+    let mut best = Duration::MAX;
+
+    for _ in 0..RUNS {
+        let now = Instant::now();
+        f()?;
+        best = best.min(now.elapsed());
+    }
+
+    Ok(best)
+}
@@ -0,0 +1,526 @@
+//! Counting letters (also n-grams and words) of text files on several threads.
+//!
+//! Besides plain threads, there are [`pool`] (rayon) and [`nonblocking`] (tokio) variants.
+
+pub mod nonblocking;
+pub mod pool;
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    hash::Hash,
+    io::{self, Read, Seek, SeekFrom},
+    mem,
+    path::Path,
+    str,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Mutex,
+    },
+    thread,
+};
+
+/// Size of piece of file taken by worker at once.
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// Bytes around chunk boundary where a word boundary is looked for.
+const TAIL_SIZE: usize = 1 << 12;
+
+#[rustfmt::skip]
+pub const ASCII_ABC: [char; 52] = [
+    'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M',
+    'N', 'O', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z',
+    'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm',
+    'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z',
+];
+
+/// Implemeter can count any type of letters (and chars).
+/// There are implementations for ASCII (fixed array), Unicode ([`HashMap`]),
+/// as well as for letter n-grams ([`Ngrams`]) and [`Words`].
+pub trait CountLetters<T> {
+    fn count_letters(&self, counts: &mut T);
+}
+
+impl CountLetters<[(char, usize); 52]> for &str {
+    /// Count ASCII letters. Expected `counts` to be sorted by chars [[A-Za-z]].
+    fn count_letters(&self, counts: &mut [(char, usize); 52]) {
+        for ch in self.chars().filter(char::is_ascii_alphabetic) {
+            // 6 chars gap between 'Z' and 'a' leads to offset
+            let offset = if ch.is_ascii_uppercase() { 0 } else { 6 };
+            // ASCII can safely be represented as u8.
+            let id = (ch as u8 - b'A' - offset) as usize;
+            // take mut count from container
+            let (_, count) = &mut counts[id];
+
+            *count += 1;
+        }
+    }
+}
+
+impl CountLetters<HashMap<char, usize>> for &str {
+    /// Count any Unicode letters (alphabetic chars).
+    fn count_letters(&self, counts: &mut HashMap<char, usize>) {
+        for ch in self.chars().filter(|ch| ch.is_alphabetic()) {
+            *counts.entry(ch).or_default() += 1;
+        }
+    }
+}
+
+/// Counts of sequences of `N` letters within words.
+#[derive(Clone, Default)]
+pub struct Ngrams<const N: usize>(pub HashMap<String, usize>);
+
+impl<const N: usize> CountLetters<Ngrams<N>> for &str {
+    fn count_letters(&self, counts: &mut Ngrams<N>) {
+        let mut letters = Vec::new();
+
+        for word in words(self) {
+            letters.clear();
+            letters.extend(word.chars());
+
+            for ngram in letters.windows(N) {
+                *counts.0.entry(ngram.iter().collect()).or_default() += 1;
+            }
+        }
+    }
+}
+
+impl<const N: usize> Ngrams<N> {
+    pub fn merge(&mut self, other: Self) {
+        merge_map(&mut self.0, other.0);
+    }
+}
+
+/// Counts of words, which are runs of letters.
+#[derive(Clone, Default)]
+pub struct Words(pub HashMap<String, usize>);
+
+impl CountLetters<Words> for &str {
+    fn count_letters(&self, counts: &mut Words) {
+        for word in words(self) {
+            match counts.0.get_mut(word) {
+                Some(count) => *count += 1,
+                None => {
+                    counts.0.insert(word.to_owned(), 1);
+                }
+            }
+        }
+    }
+}
+
+impl Words {
+    pub fn merge(&mut self, other: Self) {
+        merge_map(&mut self.0, other.0);
+    }
+}
+
+/// Split `text` into runs of letters.
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|ch: char| !ch.is_alphabetic())
+        .filter(|word| !word.is_empty())
+}
+
+pub fn merge_ascii(res: &mut [(char, usize); 52], other: [(char, usize); 52]) {
+    for ((_, count), (_, o_count)) in res.iter_mut().zip(other) {
+        *count += o_count;
+    }
+}
+
+pub fn merge_map<K>(res: &mut HashMap<K, usize>, other: HashMap<K, usize>)
+where
+    K: Hash + Eq,
+{
+    for (ch, count) in other {
+        *res.entry(ch).or_default() += count;
+    }
+}
+
+/// Count letters of `file` on `thrds` threads.
+/// Each thread starts with a copy of `init`, and results are joined by `merge`.
+///
+/// File is split into chunks of about [`CHUNK_SIZE`] bytes, cut between words.
+/// Workers read them independently, taking the next free chunk once done with previous one,
+/// so nothing is shared but the chunk counter.
+pub fn count_letters<T>(
+    file: impl AsRef<Path>,
+    thrds: usize,
+    init: T,
+    merge: impl Fn(&mut T, T),
+) -> Result<T, io::Error>
+where
+    T: Clone + Send + Sync,
+    for<'a> &'a str: CountLetters<T>,
+{
+    let path = file.as_ref();
+    let chunks = (fs::metadata(path)?.len() as usize).div_ceil(CHUNK_SIZE);
+    // position of the next chunk to be taken
+    let next = AtomicUsize::new(0);
+
+    let work = || {
+        // each worker has its own cursor
+        let mut file = File::open(path)?;
+        let mut res = init.clone();
+        let mut buf = Vec::with_capacity(CHUNK_SIZE + TAIL_SIZE + 3);
+
+        loop {
+            let pos = next.fetch_add(1, Ordering::Relaxed);
+
+            if pos >= chunks {
+                break Ok::<_, io::Error>(res);
+            }
+
+            read_chunk(&mut file, pos * CHUNK_SIZE, &mut buf)?;
+            chunk_text(&buf, pos == 0)?.count_letters(&mut res);
+        }
+    };
+
+    match thrds {
+        // unreachable due to argument restriction
+        0 => unreachable!(),
+        // proceed file on current thread
+        1 => work(),
+        // Start n worker threads (workers), no more than chunks though.
+        // Return accumulated result once all workers finished.
+        n => thread::scope(|s| {
+            let wrkrs = (0..n.min(chunks.max(1)))
+                .map(|_| s.spawn(work))
+                .collect::<Vec<_>>();
+
+            let mut res = init.clone();
+
+            for wrkr in wrkrs {
+                merge(&mut res, wrkr.join().unwrap()?);
+            }
+
+            Ok(res)
+        }),
+    }
+}
+
+/// Count letters of `reader`, which can't be split in advance (e.g. stdin), on `thrds` threads.
+/// Each thread starts with a copy of `init`, and results are joined by `merge`.
+///
+/// Reader is read sequentially by chunks of about [`CHUNK_SIZE`] bytes, cut between words.
+/// Chunks are sent to workers, so there are few large messages instead of many small ones.
+pub fn count_letters_from<T>(
+    reader: impl Read,
+    thrds: usize,
+    init: T,
+    merge: impl Fn(&mut T, T),
+) -> Result<T, io::Error>
+where
+    T: Clone + Send + Sync,
+    for<'a> &'a str: CountLetters<T>,
+{
+    let mut chunks = Chunks::new(reader);
+
+    match thrds {
+        // unreachable due to argument restriction
+        0 => unreachable!(),
+        // proceed reader on current thread
+        1 => {
+            let mut res = init;
+
+            while let Some(text) = chunks.next_text()? {
+                text.as_str().count_letters(&mut res);
+            }
+
+            Ok(res)
+        }
+        // Start n worker threads (workers), each of them takes the next chunk from channel.
+        // Bounded channel keeps reader from going too far ahead of workers.
+        n => {
+            let (tx, rx) = mpsc::sync_channel::<String>(n);
+            let rx = &Mutex::new(rx);
+            let init = &init;
+
+            thread::scope(|s| {
+                let wrkrs = (0..n)
+                    .map(|_| {
+                        s.spawn(move || {
+                            let mut res = init.clone();
+
+                            loop {
+                                // free lock immediately to give a chance to other workers
+                                let mb_text = rx.lock().unwrap().recv();
+
+                                match mb_text {
+                                    Ok(text) => text.as_str().count_letters(&mut res),
+                                    Err(_) => break res,
+                                }
+                            }
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                while let Some(text) = chunks.next_text()? {
+                    tx.send(text).unwrap();
+                }
+
+                // dropping single Sender finishes worker tasks
+                drop(tx);
+
+                let mut res = init.clone();
+
+                for wrkr in wrkrs {
+                    merge(&mut res, wrkr.join().unwrap());
+                }
+
+                Ok(res)
+            })
+        }
+    }
+}
+
+/// Sequential chunks of reader cut between words.
+struct Chunks<R> {
+    reader: R,
+    splitter: Splitter,
+}
+
+impl<R: Read> Chunks<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            splitter: Splitter::default(),
+        }
+    }
+
+    /// Read the next chunk, or return None once reader is empty.
+    fn next_text(&mut self) -> Result<Option<String>, io::Error> {
+        let mut buf = self.splitter.buf();
+        let read = (&mut self.reader)
+            .take(CHUNK_SIZE as u64)
+            .read_to_end(&mut buf)?;
+
+        self.splitter.cut(buf, read)
+    }
+}
+
+/// Cuts bytes, which are read sequentially, into chunks between words.
+#[derive(Default)]
+struct Splitter {
+    /// beginning of the next chunk which is already read
+    carry: Vec<u8>,
+}
+
+impl Splitter {
+    /// Buffer to read the next chunk into, it starts with the rest of previous one.
+    fn buf(&mut self) -> Vec<u8> {
+        mem::take(&mut self.carry)
+    }
+
+    /// Take text of `buf` once `read` bytes are appended to it,
+    /// or return None if there is nothing.
+    fn cut(&mut self, mut buf: Vec<u8>, read: usize) -> Result<Option<String>, io::Error> {
+        if buf.is_empty() {
+            return Ok(None);
+        }
+
+        // keep the last word for the next chunk unless it's the end
+        if read == CHUNK_SIZE {
+            let text = decode(&buf)?;
+            let split = text
+                .char_indices()
+                .rev()
+                .find(|(_, ch)| !ch.is_alphabetic())
+                .map_or(text.len(), |(pos, _)| pos);
+
+            self.carry = buf.split_off(split);
+        }
+
+        String::from_utf8(buf)
+            .map(Some)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+/// Read chunk starting at `start` into `buf`.
+/// Extra bytes are read to find the end of word cut by the end of chunk.
+fn read_chunk(file: &mut File, start: usize, buf: &mut Vec<u8>) -> Result<(), io::Error> {
+    buf.clear();
+    file.seek(SeekFrom::Start(start as u64))?;
+    file.take((CHUNK_SIZE + TAIL_SIZE + 3) as u64)
+        .read_to_end(buf)?;
+
+    Ok(())
+}
+
+/// Take text of chunk which is read by [`read_chunk`].
+///
+/// Chunk is moved to the first non-letter within [`TAIL_SIZE`] bytes after its nominal bounds,
+/// so neighbour chunks agree on the same place and no word is cut.
+/// If there is no such non-letter, it's moved to the first char boundary only.
+fn chunk_text(buf: &[u8], is_first: bool) -> Result<&str, io::Error> {
+    // continuation byte of UTF-8 is 0b10xx_xxxx
+    let is_cont = |byte: &u8| byte & 0b1100_0000 == 0b1000_0000;
+
+    // a char cut by the start belongs to previous chunk
+    let skip = if is_first {
+        0
+    } else {
+        buf.iter().take_while(|byte| is_cont(byte)).count()
+    };
+
+    let text = decode(&buf[skip..])?;
+
+    let begin = if is_first {
+        0
+    } else {
+        find_boundary(text, TAIL_SIZE.saturating_sub(skip)).unwrap_or(0)
+    };
+
+    // nominal end of chunk within text
+    let cut = CHUNK_SIZE - skip;
+
+    let end = if cut >= text.len() {
+        // the last chunk
+        text.len()
+    } else {
+        let mut char_cut = cut;
+
+        while !text.is_char_boundary(char_cut) {
+            char_cut += 1;
+        }
+
+        let limit = (cut + TAIL_SIZE).saturating_sub(char_cut);
+
+        char_cut + find_boundary(&text[char_cut..], limit).unwrap_or(0)
+    };
+
+    Ok(&text[begin..end.max(begin)])
+}
+
+/// Position of the first non-letter starting within `limit` bytes of `text`.
+fn find_boundary(text: &str, limit: usize) -> Option<usize> {
+    text.char_indices()
+        .take_while(|(pos, _)| *pos < limit)
+        .find(|(_, ch)| !ch.is_alphabetic())
+        .map(|(pos, _)| pos)
+}
+
+/// Decode UTF-8, a char cut by the end of `bytes` is dropped.
+fn decode(bytes: &[u8]) -> Result<&str, io::Error> {
+    let invalid = |err| io::Error::new(io::ErrorKind::InvalidData, err);
+
+    match str::from_utf8(bytes) {
+        Ok(text) => Ok(text),
+        // incomplete char
+        Err(err) if err.error_len().is_none() => {
+            str::from_utf8(&bytes[..err.valid_up_to()]).map_err(invalid)
+        }
+        Err(err) => Err(invalid(err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, io::Cursor, process};
+
+    /// Text of a bit more than two chunks, where `tail` starts `offset` bytes
+    /// before the end of the first chunk.
+    fn straddling(tail: &str, offset: usize) -> String {
+        let mut text = "ab ".repeat((CHUNK_SIZE - offset) / 3);
+        text.push_str(&" ".repeat(CHUNK_SIZE - offset - text.len()));
+        text.push_str(tail);
+
+        while text.len() < 2 * CHUNK_SIZE + TAIL_SIZE {
+            text.push_str(" ёж и ab ");
+        }
+
+        text
+    }
+
+    /// Counts of `text` written to a file and read from reader,
+    /// on 1 and several threads, on rayon pool and on tokio runtime.
+    fn count_all<T>(
+        name: &str,
+        text: &str,
+        init: T,
+        merge: impl Fn(&mut T, T) + Copy + Sync,
+    ) -> Vec<T>
+    where
+        T: Clone + Send + Sync + 'static,
+        for<'a> &'a str: CountLetters<T>,
+    {
+        let path = env::temp_dir().join(format!("l27_lf_{}_{}.txt", process::id(), name));
+        fs::write(&path, text).unwrap();
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(4)
+            .build()
+            .unwrap();
+
+        let counts = vec![
+            count_letters(&path, 1, init.clone(), merge).unwrap(),
+            count_letters(&path, 4, init.clone(), merge).unwrap(),
+            count_letters_from(Cursor::new(text), 4, init.clone(), merge).unwrap(),
+            pool::count_letters(&path, &pool, init.clone(), merge).unwrap(),
+            pool::count_letters_from(Cursor::new(text), &pool, init.clone(), merge).unwrap(),
+            rt.block_on(async {
+                let file = tokio::fs::File::open(&path).await?;
+                nonblocking::count_letters(file, 4, init.clone(), merge).await
+            })
+            .unwrap(),
+            rt.block_on(nonblocking::count_letters(
+                Cursor::new(text),
+                4,
+                init,
+                merge,
+            ))
+            .unwrap(),
+        ];
+
+        fs::remove_file(&path).unwrap();
+
+        counts
+    }
+
+    #[test]
+    fn char_across_chunks() {
+        // 'ж' takes 2 bytes, so it's cut by the end of the first chunk
+        let text = straddling("жук ", 1);
+        assert!(!text.is_char_boundary(CHUNK_SIZE));
+
+        let mut whole = HashMap::new();
+        text.as_str().count_letters(&mut whole);
+
+        for counts in count_all("char", &text, HashMap::new(), merge_map) {
+            assert!(counts == whole);
+        }
+    }
+
+    #[test]
+    fn word_across_chunks() {
+        let text = straddling("straddle жучок ", 4);
+
+        let mut whole = Words::default();
+        text.as_str().count_letters(&mut whole);
+        assert_eq!(whole.0.get("straddle"), Some(&1));
+
+        for counts in count_all("word", &text, Words::default(), Words::merge) {
+            assert!(counts.0 == whole.0);
+        }
+    }
+
+    #[test]
+    fn boundary() {
+        assert_eq!(find_boundary("abc de", 10), Some(3));
+        assert_eq!(find_boundary("abc de", 3), None);
+        assert_eq!(find_boundary("жук ", 10), Some(6));
+    }
+
+    #[test]
+    fn cut_char() {
+        let text = "ёж".as_bytes();
+
+        assert_eq!(decode(text).unwrap(), "ёж");
+        assert_eq!(decode(&text[..3]).unwrap(), "ё");
+        assert!(decode(&text[1..]).is_err());
+    }
+}
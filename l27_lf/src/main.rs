@@ -1,29 +1,26 @@
+mod output;
+
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     error::Error,
-    fs::File,
-    io::{self, BufRead},
+    fs, io,
     path::{Path, PathBuf},
-    sync::{mpsc, Arc, Mutex},
-    thread,
-    time::{Duration, Instant},
+    process,
+    time::Instant,
 };
 
-use clap::Parser;
+use clap::{error::ErrorKind, CommandFactory, Parser, ValueEnum};
+use l27_lf::{
+    count_letters, count_letters_from, merge_ascii, merge_map, nonblocking, pool, CountLetters,
+    Ngrams, Words, ASCII_ABC,
+};
+use output::{Format, Report, View};
 use serde::Serialize;
 
-#[rustfmt::skip]
-const ASCII_ABC: [char; 52] = [
-    'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M',
-    'N', 'O', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z',
-    'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm',
-    'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z',
-];
-
 #[derive(Parser)]
 pub struct Cli {
-    /// входной файл
-    pub file: PathBuf,
+    /// входные файлы, `-` или ничего - stdin
+    pub files: Vec<PathBuf>,
 
     /// количество потоков, которое будет задействовано для подсчета
     #[clap(
@@ -32,179 +29,430 @@ pub struct Cli {
         value_parser = clap::value_parser!(u8).range(1..)
     )]
     pub threads: u8,
+
+    /// какие буквы считать
+    #[clap(short, long, value_enum, default_value = "ascii")]
+    pub alphabet: Alphabet,
+
+    /// набор букв для `--alphabet custom`
+    #[clap(long, required_if_eq("alphabet", "custom"))]
+    pub letters: Option<String>,
+
+    /// не различать регистр (считать как строчные)
+    #[clap(short, long)]
+    pub ignore_case: bool,
+
+    /// сгруппировать буквы по письменностям (только для JSON)
+    #[clap(long)]
+    pub by_script: bool,
+
+    /// что считать
+    #[clap(short, long, value_enum, default_value = "letters")]
+    pub mode: Mode,
+
+    /// вывести K самых частых с долей в процентах
+    #[clap(long, conflicts_with = "by_script")]
+    pub top: Option<usize>,
+
+    /// файл со стоп-словами, которые не считаются (только для `--mode words`)
+    #[clap(long)]
+    pub stop_words: Option<PathBuf>,
+
+    /// формат вывода
+    #[clap(short, long, value_enum, default_value = "json")]
+    pub format: Format,
+
+    /// вывести затраченное время в секундах
+    #[clap(long)]
+    pub time: bool,
+
+    /// чем распараллеливать подсчет
+    #[clap(short, long, value_enum, default_value = "threads")]
+    pub runtime: Runtime,
 }
 
-#[derive(Serialize)]
-struct Output {
-    #[serde(serialize_with = "se_duration")]
-    elapsed: Duration,
-    #[serde(flatten)]
-    result: HashMap<char, usize>,
+impl Cli {
+    /// Check combinations of arguments which can't be told to clap.
+    fn validate(self) -> Result<Self, clap::Error> {
+        // rows of other formats aren't grouped
+        if self.by_script && self.format != Format::Json {
+            return Err(Cli::command().error(
+                ErrorKind::ArgumentConflict,
+                "--by-script can be used with --format json only",
+            ));
+        }
+
+        // n-grams and letters are made of words, but they aren't words themselves
+        if self.stop_words.is_some() && self.mode != Mode::Words {
+            return Err(Cli::command().error(
+                ErrorKind::ArgumentConflict,
+                "--stop-words can be used with --mode words only",
+            ));
+        }
+
+        Ok(self)
+    }
 }
 
-fn se_duration<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: serde::Serializer,
-{
-    let secs = format!("{} s", duration.as_secs_f32());
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Runtime {
+    /// потоки стандартной библиотеки
+    Threads,
+    /// задачи tokio
+    Tokio,
+    /// пул потоков rayon
+    Rayon,
+}
 
-    serializer.serialize_str(&secs)
+/// Runtime ready to count, see [`Runtime`].
+enum Exec {
+    Threads(usize),
+    /// runtime, and number of tasks at once
+    Tokio(tokio::runtime::Runtime, usize),
+    Rayon(rayon::ThreadPool),
 }
 
-impl Output {
-    fn from<T>(counts: T, elapsed: Duration) -> Self
-    where
-        T: IntoIterator<Item = (char, usize)>,
-    {
-        let result = counts.into_iter().collect();
+impl Exec {
+    fn build(runtime: Runtime, threads: usize) -> Result<Self, Box<dyn Error>> {
+        let exec = match runtime {
+            Runtime::Threads => Self::Threads(threads),
+            Runtime::Tokio => {
+                let rt = tokio::runtime::Builder::new_multi_thread()
+                    .worker_threads(threads)
+                    .max_blocking_threads(threads)
+                    .build()?;
+
+                Self::Tokio(rt, threads)
+            }
+            Runtime::Rayon => {
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()?;
+
+                Self::Rayon(pool)
+            }
+        };
 
-        Self { result, elapsed }
+        Ok(exec)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Mode {
+    /// отдельные буквы
+    Letters,
+    /// пары букв внутри слова
+    Bigrams,
+    /// тройки букв внутри слова
+    Trigrams,
+    /// слова
+    Words,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Alphabet {
+    /// латинские буквы ASCII
+    Ascii,
+    /// любые буквы Unicode
+    Unicode,
+    /// буквы кириллицы
+    Cyrillic,
+    /// буквы из `--letters`
+    Custom,
+}
+
+/// Writing system of letter, only few of them are told apart.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Other,
+}
+
+impl Script {
+    fn of(ch: char) -> Self {
+        match ch as u32 {
+            0x0041..=0x005A | 0x0061..=0x007A => Self::Latin,
+            // Latin-1 Supplement, Latin Extended-A/B, IPA
+            0x00C0..=0x02AF => Self::Latin,
+            0x1E00..=0x1EFF | 0xFF21..=0xFF3A | 0xFF41..=0xFF5A => Self::Latin,
+            0x0370..=0x03FF | 0x1F00..=0x1FFF => Self::Greek,
+            0x0400..=0x052F | 0x1C80..=0x1C8F | 0x2DE0..=0x2DFF | 0xA640..=0xA69F => Self::Cyrillic,
+            _ => Self::Other,
+        }
     }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     // parse and prepare arguments
-    let (file, threads) = parse_args();
+    let (files, threads, runtime, mode, letters, view, time) = parse_args()?;
+    let exec = Exec::build(runtime, threads)?;
 
-    // get counts of ASCII letters
+    // get counts of letters (or their sequences) of each input
     // and measure taken time
-    let (counts, taken_time) = {
-        let now = Instant::now();
-        let counts = count_ascii_letters(file, threads)?;
-        let taken = now.elapsed();
-        (counts, taken)
-    };
+    let now = Instant::now();
+    let mut inputs = Vec::with_capacity(files.len());
+    // report all bad inputs, but don't stop on them
+    let mut failed = false;
+
+    for file in files {
+        match count_input(&file, &exec, mode, &letters) {
+            Ok(counts) => inputs.push((file.display().to_string(), counts)),
+            Err(err) => {
+                eprintln!("l27_lf: {}: {}", file.display(), err);
+                failed = true;
+            }
+        }
+    }
 
-    // create output model and serialize it into string
-    let json = {
-        let output = Output::from(counts, taken_time);
-        serde_json::to_string(&output)?
+    let report = Report {
+        inputs,
+        elapsed: time.then(|| now.elapsed()),
     };
 
-    // print result as json
-    Ok(println!("{}", json))
+    // print result in asked format
+    report.print(&view)?;
+
+    if failed {
+        process::exit(1);
+    }
+
+    Ok(())
 }
 
-fn parse_args() -> (impl AsRef<Path>, usize) {
-    let Cli { file, threads } = Cli::parse();
+/// Count letters (or their sequences) of `file` according to `mode`.
+fn count_input(
+    file: &Path,
+    exec: &Exec,
+    mode: Mode,
+    letters: &Letters,
+) -> Result<HashMap<String, usize>, io::Error> {
+    let counts = match (mode, letters.alphabet) {
+        // fixed set of letters is the fastest way
+        (Mode::Letters, Alphabet::Ascii) => {
+            let init = ASCII_ABC.map(|ch| (ch, 0));
+            let counts = count_any(file, exec, init, merge_ascii)?;
+            letters.select(counts.map(|(ch, count)| (ch.to_string(), count)))
+        }
+        (Mode::Letters, _) => {
+            let counts = count_any(file, exec, HashMap::new(), merge_map)?;
+            letters.select(
+                counts
+                    .into_iter()
+                    .map(|(ch, count)| (ch.to_string(), count)),
+            )
+        }
+        (Mode::Bigrams, _) => {
+            let Ngrams(counts) = count_any(file, exec, Ngrams::<2>::default(), Ngrams::merge)?;
+            letters.select(counts)
+        }
+        (Mode::Trigrams, _) => {
+            let Ngrams(counts) = count_any(file, exec, Ngrams::<3>::default(), Ngrams::merge)?;
+            letters.select(counts)
+        }
+        (Mode::Words, _) => {
+            let Words(counts) = count_any(file, exec, Words::default(), Words::merge)?;
+            letters.select(counts)
+        }
+    };
 
-    (file, threads as usize)
+    Ok(counts)
 }
 
-/// Implemeter can count any type of letters (and chars).
-/// For instance, implementation for Unicode could look like this:
-/// ```
-/// impl CountLetters<std::collection::HashMap<char, usize>> for &str {
-///     fn count_letters(&self, counts: &mut T) {
-///         todo!()
-///     }
-/// }
-/// ```
-trait CountLetters<T>
+/// Count file by chunks, or stdin if `file` is `-`, on chosen runtime.
+fn count_any<T>(
+    file: &Path,
+    exec: &Exec,
+    init: T,
+    merge: impl Fn(&mut T, T) + Sync,
+) -> Result<T, io::Error>
 where
-    T: IntoIterator<Item = (char, usize)>,
+    T: Clone + Send + Sync + 'static,
+    for<'a> &'a str: CountLetters<T>,
 {
-    fn count_letters(&self, counts: &mut T);
-}
-
-impl CountLetters<[(char, usize); 52]> for &str {
-    /// Count ASCII letters. Expected `counts` to be sorted by chars [[A-Za-z]].
-    fn count_letters(&self, counts: &mut [(char, usize); 52]) {
-        for ch in self.chars().filter(char::is_ascii_alphabetic) {
-            // 6 chars gap between 'Z' and 'a' leads to offset
-            let offset = if ch.is_ascii_uppercase() { 0 } else { 6 };
-            // ASCII can safely be represented as u8.
-            let id = (ch as u8 - b'A' - offset) as usize;
-            // take mut count from container
-            let (_, count) = &mut counts[id];
+    let is_stdin = file.as_os_str() == "-";
 
-            *count += 1;
+    match exec {
+        Exec::Threads(threads) if is_stdin => {
+            count_letters_from(io::stdin().lock(), *threads, init, merge)
         }
+        Exec::Threads(threads) => count_letters(file, *threads, init, merge),
+        Exec::Tokio(rt, tasks) => rt.block_on(async {
+            if is_stdin {
+                nonblocking::count_letters(tokio::io::stdin(), *tasks, init, merge).await
+            } else {
+                let file = tokio::fs::File::open(file).await?;
+                nonblocking::count_letters(file, *tasks, init, merge).await
+            }
+        }),
+        Exec::Rayon(pool) if is_stdin => pool::count_letters_from(io::stdin(), pool, init, merge),
+        Exec::Rayon(pool) => pool::count_letters(file, pool, init, merge),
     }
 }
 
-fn count_ascii_letters(
-    file: impl AsRef<Path>,
-    thrds: usize,
-) -> Result<[(char, usize); 52], io::Error> {
-    let file = File::open(file)?;
-
-    let mut res = ASCII_ABC.map(|ch| (ch, 0));
+/// Inputs, number of threads, runtime, mode, letters, view and whether to time counting.
+type Args = (Vec<PathBuf>, usize, Runtime, Mode, Letters, View, bool);
+
+fn parse_args() -> Result<Args, io::Error> {
+    let Cli {
+        mut files,
+        threads,
+        alphabet,
+        letters,
+        ignore_case,
+        by_script,
+        mode,
+        top,
+        stop_words,
+        format,
+        time,
+        runtime,
+    } = Cli::parse().validate().unwrap_or_else(|err| err.exit());
+
+    // read stdin if nothing is given
+    if files.is_empty() {
+        files.push(PathBuf::from("-"));
+    }
 
-    match thrds {
-        // unreachable due to argument restriction
-        0 => unreachable!(),
-        // proceed file on current thread
-        1 => {
-            for try_line in io::BufReader::new(file).lines() {
-                try_line?.as_str().count_letters(&mut res);
+    let custom = letters
+        .unwrap_or_default()
+        .chars()
+        .map(|ch| if ignore_case { fold(ch) } else { ch })
+        .collect();
+
+    // any whitespace separates stop words
+    let stop_words = stop_words
+        .map(|path| {
+            fs::read_to_string(&path)
+                .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))
+        })
+        .transpose()?
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(|word| {
+            if ignore_case {
+                fold_str(word)
+            } else {
+                word.to_owned()
             }
-        }
-        // Start n worker threads (workers).
-        // Each worker proceeds recieved line.
-        // Return accumulated result once all workers finished.
-        n => {
-            // Main thread is Sender. Each worker gets same Receiver.
-            // This approach allows to take out a line 
-            // from Receier and unlock it for other workers.
-            let (tx, rx) = {
-                let (tx, rx) = mpsc::channel::<String>();
-                (tx, Arc::new(Mutex::new(rx)))
+        })
+        .collect();
+
+    let letters = Letters {
+        alphabet,
+        custom,
+        ignore_case,
+        stop_words,
+    };
+
+    let view = View {
+        format,
+        by_script,
+        top,
+    };
+
+    Ok((files, threads as usize, runtime, mode, letters, view, time))
+}
+
+/// Which of counted letters go to output.
+struct Letters {
+    alphabet: Alphabet,
+    /// used by [`Alphabet::Custom`] only
+    custom: HashSet<char>,
+    ignore_case: bool,
+    /// never go to output
+    stop_words: HashSet<String>,
+}
+
+impl Letters {
+    /// Fold case if needed and keep keys made of letters of alphabet only.
+    fn select<T>(&self, counts: T) -> HashMap<String, usize>
+    where
+        T: IntoIterator<Item = (String, usize)>,
+    {
+        let mut res = HashMap::new();
+
+        for (key, count) in counts {
+            let key = if self.ignore_case {
+                fold_str(&key)
+            } else {
+                key
             };
 
-            // known count of workers - init with cap
-            let mut wrkrs = Vec::with_capacity(n);
-
-            for _ in 0..n {
-                // keep handlers to sync once done
-                let wrkr = thread::spawn({
-                    let rx_ = Arc::clone(&rx);
-                    // note: copy by value
-                    let mut w_res = res;
-
-                    move || loop {
-                        // free lock immediately to give a chance to other workers
-                        let mb_line = {
-                            let lock = rx_.lock().unwrap();
-                            lock.iter().next()
-                        };
-
-                        if let Some(line) = mb_line.as_deref() {
-                            line.count_letters(&mut w_res);
-                            continue;
-                        }
-
-                        break w_res;
-                    }
-                });
-
-                wrkrs.push(wrkr);
-            }
+            let keep = key.chars().all(|ch| match self.alphabet {
+                Alphabet::Ascii => ch.is_ascii_alphabetic(),
+                Alphabet::Unicode => true,
+                Alphabet::Cyrillic => Script::of(ch) == Script::Cyrillic,
+                Alphabet::Custom => self.custom.contains(&ch),
+            });
 
-            // read and send line to workers
-            for try_line in io::BufReader::new(file).lines() {
-                tx.send(try_line?).unwrap();
+            if keep && !self.stop_words.contains(&key) {
+                *res.entry(key).or_default() += count;
             }
+        }
 
-            // dropping single Sender finishes worker tasks
-            drop(tx);
+        res
+    }
+}
 
-            // n x 52 = O(n) complexity even there is a nested loop
-            for wrkr in wrkrs {
-                for (pos, w_count) in wrkr
-                    .join()
-                    .unwrap()
-                    .map(|(_, count)| count)
-                    .into_iter()
-                    .enumerate()
-                {
-                    let (_, count) = &mut res[pos];
+/// Lowercase `ch` if it stays a single char.
+fn fold(ch: char) -> char {
+    let mut lower = ch.to_lowercase();
 
-                    *count += w_count;
-                }
-            }
+    match (lower.next(), lower.next()) {
+        (Some(low), None) => low,
+        _ => ch,
+    }
+}
+
+/// Fold case of each char of `str`, see [`fold`].
+fn fold_str(str: &str) -> String {
+    str.chars().map(fold).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(args: &str) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(args.split_whitespace())?.validate()
+    }
+
+    #[test]
+    fn stop_words_of_words_only() {
+        assert!(validate("l27_lf --mode words --stop-words stop.txt").is_ok());
+
+        for mode in ["letters", "bigrams", "trigrams"] {
+            let err = validate(&format!("l27_lf --mode {} --stop-words stop.txt", mode))
+                .err()
+                .unwrap();
+            assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
         }
     }
 
-    Ok(res)
+    #[test]
+    fn by_script_of_json_only() {
+        assert!(validate("l27_lf --by-script").is_ok());
+
+        let err = validate("l27_lf --by-script --format table").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn stop_words_filter_words() {
+        let letters = Letters {
+            alphabet: Alphabet::Unicode,
+            custom: HashSet::new(),
+            ignore_case: true,
+            stop_words: HashSet::from(["the".to_owned()]),
+        };
+        let counts = [("The".to_owned(), 2), ("them".to_owned(), 1)];
+
+        assert_eq!(
+            letters.select(counts),
+            HashMap::from([("them".to_owned(), 1)])
+        );
+    }
 }
@@ -0,0 +1,63 @@
+//! Counting with tokio tasks.
+
+use std::io;
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    task::JoinSet,
+};
+
+use crate::{CountLetters, Splitter, CHUNK_SIZE};
+
+/// Count letters of `reader` with no more than `tasks` tokio tasks at once.
+/// Each task starts with a copy of `init`, and results are joined by `merge`.
+///
+/// Reader is read by chunks like [`crate::count_letters_from`] does.
+/// Counting is done by blocking tasks, so it doesn't stall workers of runtime.
+pub async fn count_letters<T, R>(
+    mut reader: R,
+    tasks: usize,
+    init: T,
+    merge: impl Fn(&mut T, T),
+) -> Result<T, io::Error>
+where
+    R: AsyncRead + Unpin,
+    T: Clone + Send + 'static,
+    for<'a> &'a str: CountLetters<T>,
+{
+    let mut splitter = Splitter::default();
+    let mut set = JoinSet::new();
+    let mut res = init.clone();
+
+    loop {
+        let mut buf = splitter.buf();
+        let read = (&mut reader)
+            .take(CHUNK_SIZE as u64)
+            .read_to_end(&mut buf)
+            .await?;
+
+        let Some(text) = splitter.cut(buf, read)? else {
+            break;
+        };
+
+        // wait for a free slot
+        if set.len() >= tasks.max(1) {
+            if let Some(counts) = set.join_next().await {
+                merge(&mut res, counts.map_err(io::Error::other)?);
+            }
+        }
+
+        let mut counts = init.clone();
+
+        set.spawn_blocking(move || {
+            text.as_str().count_letters(&mut counts);
+            counts
+        });
+    }
+
+    while let Some(counts) = set.join_next().await {
+        merge(&mut res, counts.map_err(io::Error::other)?);
+    }
+
+    Ok(res)
+}
@@ -0,0 +1,279 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    io::{self, Write},
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use serde::{ser::SerializeMap, Serialize};
+
+use crate::Script;
+
+/// Width of the longest bar of histogram.
+const BAR_WIDTH: usize = 50;
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Format {
+    /// JSON объект
+    Json,
+    /// строки CSV
+    Csv,
+    /// выровненная таблица
+    Table,
+    /// гистограмма в терминале
+    Histogram,
+}
+
+/// Counts of each input, and taken time if it's asked for.
+pub struct Report {
+    pub inputs: Vec<(String, HashMap<String, usize>)>,
+    pub elapsed: Option<Duration>,
+}
+
+/// Which part of counts to show and how.
+pub struct View {
+    pub format: Format,
+    /// JSON only, it's checked by CLI
+    pub by_script: bool,
+    pub top: Option<usize>,
+}
+
+impl Report {
+    /// Sum of all inputs.
+    fn total(&self) -> HashMap<String, usize> {
+        let mut total = HashMap::new();
+
+        for (_, counts) in &self.inputs {
+            for (key, count) in counts {
+                *total.entry(key.clone()).or_default() += count;
+            }
+        }
+
+        total
+    }
+
+    /// Print per input counts, and aggregate counts if there are several inputs.
+    pub fn print(self, view: &View) -> Result<(), Box<dyn Error>> {
+        let out = &mut io::stdout().lock();
+
+        if view.format == Format::Json {
+            let output = Output::from(self, view);
+            serde_json::to_writer(&mut *out, &output)?;
+            writeln!(out)?;
+
+            return Ok(());
+        }
+
+        let total = (self.inputs.len() > 1).then(|| self.total());
+        let sections = self
+            .inputs
+            .iter()
+            .map(|(name, counts)| (name.as_str(), counts))
+            .chain(total.as_ref().map(|counts| ("total", counts)));
+
+        if view.format == Format::Csv {
+            writeln!(out, "input,key,count,percent")?;
+        }
+
+        for (name, counts) in sections {
+            let ranked = rank(counts, view.top);
+
+            match view.format {
+                Format::Json => unreachable!(),
+                Format::Csv => write_csv(out, name, &ranked)?,
+                Format::Table => write_table(out, name, &ranked)?,
+                Format::Histogram => write_histogram(out, name, &ranked)?,
+            }
+        }
+
+        if let Some(elapsed) = self.elapsed {
+            // keep CSV rows clean
+            if view.format == Format::Csv {
+                eprintln!("elapsed: {} s", elapsed.as_secs_f64());
+            } else {
+                writeln!(out, "elapsed: {} s", elapsed.as_secs_f64())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Count and its percentage of total.
+#[derive(Serialize)]
+struct Share {
+    count: usize,
+    percent: f64,
+}
+
+/// Sort `counts` by the most frequent first, then alphabetically,
+/// and take `top` of them if it's given. Keys never met are left out.
+fn rank(counts: &HashMap<String, usize>, top: Option<usize>) -> Vec<(String, Share)> {
+    let total = counts.values().sum::<usize>().max(1);
+    let mut counts = Vec::from_iter(counts.iter().filter(|(_, &count)| count > 0));
+
+    counts
+        .sort_unstable_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+
+    counts
+        .into_iter()
+        .take(top.unwrap_or(usize::MAX))
+        .map(|(key, &count)| {
+            let percent = count as f64 * 100.0 / total as f64;
+            (key.clone(), Share { count, percent })
+        })
+        .collect()
+}
+
+fn write_csv(out: &mut impl Write, name: &str, ranked: &[(String, Share)]) -> io::Result<()> {
+    // keys are made of letters, but name of input is arbitrary
+    let name = if name.contains([',', '"', '\n']) {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    } else {
+        name.to_owned()
+    };
+
+    for (key, Share { count, percent }) in ranked {
+        writeln!(out, "{},{},{},{:.4}", name, key, count, percent)?;
+    }
+
+    Ok(())
+}
+
+fn write_table(out: &mut impl Write, name: &str, ranked: &[(String, Share)]) -> io::Result<()> {
+    let key_width = ranked
+        .iter()
+        .map(|(key, _)| key.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(3);
+
+    writeln!(out, "== {} ==", name)?;
+    writeln!(out, "{:<key_width$} {:>10} {:>8}", "key", "count", "%")?;
+
+    for (key, Share { count, percent }) in ranked {
+        writeln!(out, "{:<key_width$} {:>10} {:>8.2}", key, count, percent)?;
+    }
+
+    writeln!(out)
+}
+
+fn write_histogram(out: &mut impl Write, name: &str, ranked: &[(String, Share)]) -> io::Result<()> {
+    let key_width = ranked
+        .iter()
+        .map(|(key, _)| key.chars().count())
+        .max()
+        .unwrap_or(0);
+    // ranked is sorted, so the first is the largest
+    let max = ranked.first().map_or(1, |(_, share)| share.count.max(1));
+
+    writeln!(out, "== {} ==", name)?;
+
+    for (key, Share { count, percent }) in ranked {
+        let bar = "█".repeat(count * BAR_WIDTH / max);
+
+        writeln!(
+            out,
+            "{:<key_width$} │{:<BAR_WIDTH$} {} ({:.2}%)",
+            key, bar, count, percent
+        )?;
+    }
+
+    writeln!(out)
+}
+
+#[derive(Serialize)]
+struct Output {
+    /// seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    elapsed: Option<f64>,
+    #[serde(flatten)]
+    body: Body,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Body {
+    /// counts of single input have their own key, so they never clash with `elapsed`
+    Single { counts: Counts },
+    Multi {
+        #[serde(serialize_with = "se_ordered")]
+        files: Vec<(String, Counts)>,
+        total: Counts,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Counts {
+    Flat(HashMap<String, usize>),
+    ByScript(BTreeMap<Script, HashMap<String, usize>>),
+    #[serde(serialize_with = "se_ordered")]
+    Top(Vec<(String, Share)>),
+}
+
+/// Serialize pairs as map keeping their order.
+fn se_ordered<S, V>(pairs: &[(String, V)], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    V: Serialize,
+{
+    let mut map = serializer.serialize_map(Some(pairs.len()))?;
+
+    for (key, val) in pairs {
+        map.serialize_entry(key, val)?;
+    }
+
+    map.end()
+}
+
+impl Output {
+    fn from(report: Report, view: &View) -> Self {
+        let elapsed = report.elapsed.map(|elapsed| elapsed.as_secs_f64());
+
+        let body = if report.inputs.len() > 1 {
+            let total = Counts::from(report.total(), view);
+            let files = report
+                .inputs
+                .into_iter()
+                .map(|(name, counts)| (name, Counts::from(counts, view)))
+                .collect();
+
+            Body::Multi { files, total }
+        } else {
+            let counts = report
+                .inputs
+                .into_iter()
+                .next()
+                .map(|(_, counts)| counts)
+                .unwrap_or_default();
+
+            Body::Single {
+                counts: Counts::from(counts, view),
+            }
+        };
+
+        Self { elapsed, body }
+    }
+}
+
+impl Counts {
+    fn from(counts: HashMap<String, usize>, view: &View) -> Self {
+        if view.top.is_some() {
+            Self::Top(rank(&counts, view.top))
+        } else if view.by_script {
+            let mut groups = BTreeMap::<_, HashMap<_, _>>::new();
+
+            for (key, count) in counts {
+                // n-grams and words are grouped by their first letter
+                let script = key.chars().next().map_or(Script::Other, Script::of);
+                groups.entry(script).or_default().insert(key, count);
+            }
+
+            Self::ByScript(groups)
+        } else {
+            Self::Flat(counts)
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Counting on rayon thread pool.
+
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    iter,
+    path::Path,
+};
+
+use rayon::{prelude::*, ThreadPool};
+
+use crate::{chunk_text, read_chunk, Chunks, CountLetters, CHUNK_SIZE};
+
+/// Count letters of `file` on `pool`.
+/// Chunks are the same as of [`crate::count_letters`], but they're scheduled by rayon.
+pub fn count_letters<T>(
+    file: impl AsRef<Path>,
+    pool: &ThreadPool,
+    init: T,
+    merge: impl Fn(&mut T, T) + Sync,
+) -> Result<T, io::Error>
+where
+    T: Clone + Send + Sync,
+    for<'a> &'a str: CountLetters<T>,
+{
+    let path = file.as_ref();
+    let chunks = (fs::metadata(path)?.len() as usize).div_ceil(CHUNK_SIZE);
+
+    pool.install(|| {
+        (0..chunks)
+            .into_par_iter()
+            // file is opened once per fold, not per chunk
+            .try_fold(
+                || (None, init.clone()),
+                |(mut mb_file, mut res), pos| {
+                    let (file, buf) = match &mut mb_file {
+                        Some(file) => file,
+                        None => mb_file.insert((File::open(path)?, Vec::new())),
+                    };
+
+                    read_chunk(file, pos * CHUNK_SIZE, buf)?;
+                    chunk_text(buf, pos == 0)?.count_letters(&mut res);
+
+                    Ok::<_, io::Error>((mb_file, res))
+                },
+            )
+            .map(|acc| acc.map(|(_, res)| res))
+            .try_reduce(
+                || init.clone(),
+                |mut res, other| {
+                    merge(&mut res, other);
+                    Ok(res)
+                },
+            )
+    })
+}
+
+/// Count letters of `reader`, which can't be split in advance (e.g. stdin), on `pool`.
+/// Chunks are the same as of [`crate::count_letters_from`].
+pub fn count_letters_from<T>(
+    reader: impl Read + Send,
+    pool: &ThreadPool,
+    init: T,
+    merge: impl Fn(&mut T, T) + Sync,
+) -> Result<T, io::Error>
+where
+    T: Clone + Send + Sync,
+    for<'a> &'a str: CountLetters<T>,
+{
+    let mut chunks = Chunks::new(reader);
+
+    pool.install(|| {
+        iter::from_fn(|| chunks.next_text().transpose())
+            .par_bridge()
+            .try_fold(
+                || init.clone(),
+                |mut res, text| {
+                    text?.as_str().count_letters(&mut res);
+                    Ok::<_, io::Error>(res)
+                },
+            )
+            .try_reduce(
+                || init.clone(),
+                |mut res, other| {
+                    merge(&mut res, other);
+                    Ok(res)
+                },
+            )
+    })
+}
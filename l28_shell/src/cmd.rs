@@ -1,5 +1,5 @@
+use crate::{job::job_parser, ps::Column};
 use clap::{Parser, Subcommand};
-use nix::{sys::signal::Signal, unistd::Pid};
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -9,7 +9,7 @@ pub struct Cli {
     pub cmd: Cmd,
 }
 
-#[derive(Debug, Subcommand)]
+#[derive(Debug, PartialEq, Subcommand)]
 pub enum Cmd {
     #[command(hide = true)]
     Ext {
@@ -23,18 +23,107 @@ pub enum Cmd {
         string: String,
     },
     Cd {
-        path: PathBuf,
+        path: Option<PathBuf>,
     },
     Exec {
         comm: String,
         #[arg(allow_hyphen_values = true)]
         args: Vec<String>,
     },
+    /// Sends a signal to processes or jobs (`%N`), `-l` lists signals
     Kill {
-        signal: Signal,
-        #[arg(value_parser = clap::value_parser!(i32))]
-        pid: Pid,
+        #[arg(allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Lists processes of the session
+    Ps {
+        /// Lists all processes
+        #[arg(short = 'e')]
+        all: bool,
+        /// Columns separated by commas
+        #[arg(
+            short = 'o',
+            value_enum,
+            value_delimiter = ',',
+            default_value = "pid,state,elapsed,cmdline"
+        )]
+        columns: Vec<Column>,
+    },
+    /// Marks variables for export to commands, `NAME=VALUE` sets them too
+    Export {
+        vars: Vec<String>,
+    },
+    /// Removes variables, or functions with `-f`
+    Unset {
+        #[arg(short = 'f')]
+        functions: bool,
+        names: Vec<String>,
+    },
+    /// Defines aliases given as `NAME=VALUE`, prints the others
+    Alias {
+        defs: Vec<String>,
+    },
+    /// Removes aliases, `-a` removes all of them
+    Unalias {
+        #[arg(short = 'a')]
+        all: bool,
+        names: Vec<String>,
+    },
+    /// Checks files and compares strings and integers
+    #[command(disable_help_flag = true)]
+    Test {
+        #[arg(allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// `test` with the last argument `]`
+    #[command(name = "[", disable_help_flag = true)]
+    Bracket {
+        #[arg(allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Returns from a function
+    Return {
+        code: Option<i32>,
+    },
+    /// Prints exported variables
+    Env,
+    /// Turns options on with `-` and off with `+`: `e` exits on failure,
+    /// `x` prints commands; `--` replaces positional parameters
+    Set {
+        #[arg(allow_hyphen_values = true)]
+        options: Vec<String>,
+    },
+    /// Lists background and stopped jobs
+    Jobs,
+    /// Continues a job in the foreground
+    Fg {
+        #[arg(value_parser = job_parser)]
+        job: Option<usize>,
     },
-    Ps,
-    Exit,
+    /// Continues a stopped job in the background
+    Bg {
+        #[arg(value_parser = job_parser)]
+        job: Option<usize>,
+    },
+    /// Waits for jobs to finish
+    Wait {
+        #[arg(value_parser = job_parser)]
+        jobs: Vec<usize>,
+    },
+    Exit {
+        code: Option<i32>,
+    },
+}
+
+impl From<Vec<String>> for Cmd {
+    fn from(args: Vec<String>) -> Self {
+        Cli::try_parse_from(&args)
+            .map(|cli| cli.cmd)
+            .unwrap_or_else(|_| {
+                let mut args = args.into_iter();
+                let comm = args.next().unwrap_or_default();
+                let args = args.collect();
+                Cmd::Ext { comm, args }
+            })
+    }
 }
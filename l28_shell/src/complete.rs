@@ -0,0 +1,131 @@
+use crate::{cmd::Cli, shell::Shell};
+use clap::CommandFactory;
+use std::{collections::BTreeSet, fs, os::unix::fs::PermissionsExt, path::Path};
+
+/// Collects completions of the word: aliases, functions, builtins and
+/// executables on `$PATH` in the command position, filesystem paths otherwise.
+pub fn candidates(word: &str, command: bool, sh: &Shell) -> Vec<String> {
+    let mut found = BTreeSet::new();
+
+    if command && !word.contains('/') {
+        let names = sh.aliases.keys().chain(sh.functions.keys()).cloned();
+        found.extend(
+            names
+                .chain(builtins())
+                .filter(|name| name.starts_with(word)),
+        );
+        found.extend(executables(word, sh));
+    } else {
+        found.extend(paths(word, sh));
+    }
+
+    found.into_iter().collect()
+}
+
+/// Escapes chars which the parser takes specially, so that the name is read
+/// back as one word.
+pub fn escape_name(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+
+    for c in name.chars() {
+        if c.is_whitespace() || "\\'\"`$|&;<>(){}[]*?!#".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+/// Takes the escaped chars of the typed word literally.
+pub fn unescape_word(word: &str) -> String {
+    let mut chars = word.chars();
+    let mut unescaped = String::with_capacity(word.len());
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+/// Names of the builtins from `Cmd`.
+fn builtins() -> Vec<String> {
+    Cli::command()
+        .get_subcommands()
+        .filter(|cmd| !cmd.is_hide_set())
+        .map(|cmd| cmd.get_name().to_owned())
+        .collect()
+}
+
+fn executables(prefix: &str, sh: &Shell) -> Vec<String> {
+    let path = sh.vars.get("PATH").unwrap_or_default();
+
+    path.split(':')
+        .filter(|dir| !dir.is_empty())
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let meta = fs::metadata(entry.path()).ok()?;
+
+            (name.starts_with(prefix) && meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+                .then_some(name)
+        })
+        .collect()
+}
+
+/// Entries of the directory the word points to, directories end with `/`.
+fn paths(word: &str, sh: &Shell) -> Vec<String> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(pos) => word.split_at(pos + 1),
+        None => ("", word),
+    };
+    let lookup = match (dir.strip_prefix('~'), sh.vars.get("HOME")) {
+        (Some(rest), Some(home)) if rest.starts_with('/') => format!("{}{}", home, rest),
+        _ if dir.is_empty() => ".".to_owned(),
+        _ => dir.to_owned(),
+    };
+
+    let Ok(entries) = fs::read_dir(Path::new(&lookup)) else {
+        return vec![];
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+
+            if !name.starts_with(prefix) || name.starts_with('.') && !prefix.starts_with('.') {
+                return None;
+            }
+
+            let slash = match fs::metadata(entry.path()) {
+                Ok(meta) if meta.is_dir() => "/",
+                _ => "",
+            };
+
+            Some(format!("{}{}{}", dir, name, slash))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape_name("plain.txt"), "plain.txt");
+        assert_eq!(escape_name("my file's (1).txt"), r"my\ file\'s\ \(1\).txt");
+        assert_eq!(escape_name("~/a b/"), r"~/a\ b/");
+
+        for name in ["my file's (1).txt", r"back\slash", "$HOME"] {
+            assert_eq!(unescape_word(&escape_name(name)), name);
+        }
+    }
+}
@@ -0,0 +1,572 @@
+use crate::{
+    complete::{candidates, escape_name, unescape_word},
+    shell::Shell,
+};
+use anyhow::Result;
+use nix::{
+    errno::Errno,
+    sys::termios::{
+        tcgetattr, tcsetattr, InputFlags, LocalFlags, SetArg, SpecialCharacterIndices, Termios,
+    },
+    unistd::read,
+};
+use std::{
+    fs,
+    io::{self, Write},
+    os::fd::AsRawFd,
+    path::PathBuf,
+};
+
+/// Number of history entries kept.
+const HISTORY_SIZE: usize = 1000;
+
+/// Line editor of the interactive shell with history and completion.
+pub struct Editor {
+    history: Vec<String>,
+    /// File the history is loaded from and saved to.
+    file: Option<PathBuf>,
+}
+
+/// Line is dropped with `Ctrl-C`.
+#[derive(Debug, thiserror::Error)]
+#[error("interrupted")]
+pub struct Interrupted;
+
+/// Terminal in raw mode, restored when dropped.
+struct Raw(Termios);
+
+impl Raw {
+    fn enable() -> Result<Self> {
+        let orig = tcgetattr(io::stdin())?;
+        let mut raw = orig.clone();
+
+        raw.local_flags
+            .remove(LocalFlags::ICANON | LocalFlags::ECHO | LocalFlags::ISIG | LocalFlags::IEXTEN);
+        raw.input_flags.remove(InputFlags::ICRNL | InputFlags::IXON);
+        raw.control_chars[SpecialCharacterIndices::VMIN as usize] = 1;
+        raw.control_chars[SpecialCharacterIndices::VTIME as usize] = 0;
+        tcsetattr(io::stdin(), SetArg::TCSANOW, &raw)?;
+
+        Ok(Self(orig))
+    }
+}
+
+impl Drop for Raw {
+    fn drop(&mut self) {
+        let _ = tcsetattr(io::stdin(), SetArg::TCSANOW, &self.0);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Key {
+    Char(char),
+    Ctrl(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    Eof,
+    Unknown,
+}
+
+/// Text being edited and the cursor in it.
+#[derive(Default)]
+struct Buffer {
+    chars: Vec<char>,
+    pos: usize,
+    /// Row the cursor was left on by the last rendering, the text of a
+    /// multi-line command from history takes several of them.
+    row: usize,
+}
+
+impl Buffer {
+    fn set(&mut self, s: &str) {
+        self.chars = s.chars().collect();
+        self.pos = self.chars.len();
+    }
+
+    fn insert(&mut self, s: &str) {
+        for c in s.chars() {
+            self.chars.insert(self.pos, c);
+            self.pos += 1;
+        }
+    }
+
+    fn backspace(&mut self) {
+        if self.pos > 0 {
+            self.pos -= 1;
+            self.chars.remove(self.pos);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.pos < self.chars.len() {
+            self.chars.remove(self.pos);
+        }
+    }
+
+    /// Deletes the word before the cursor.
+    fn delete_word(&mut self) {
+        let mut start = self.pos;
+
+        while start > 0 && self.chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        while start > 0 && !self.chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+
+        self.chars.drain(start..self.pos);
+        self.pos = start;
+    }
+
+    fn before(&self) -> String {
+        self.chars[..self.pos].iter().collect()
+    }
+
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+}
+
+impl Editor {
+    /// Creates the editor loading history from the file if it exists.
+    pub fn new(file: Option<PathBuf>) -> Self {
+        let mut history = file
+            .as_ref()
+            .and_then(|file| fs::read_to_string(file).ok())
+            .map(|s| load(&s))
+            .unwrap_or_default();
+
+        history.drain(..history.len().saturating_sub(HISTORY_SIZE));
+
+        Self { history, file }
+    }
+
+    /// Reads a line into the buffer like `Stdin::read_line` does, zero bytes
+    /// read means end of input, [`Interrupted`] that the line is dropped.
+    pub fn read_line(&mut self, prompt: &str, buf: &mut String, sh: &Shell) -> Result<usize> {
+        let raw = Raw::enable()?;
+        let mut line = Buffer::default();
+        // Position in history, its length stands for the new line.
+        let mut index = self.history.len();
+        let mut saved = String::new();
+
+        render(prompt, &mut line)?;
+
+        loop {
+            match read_key()? {
+                Key::Enter => break,
+                // Ctrl-D ends input on an empty line only, while the end of
+                // the terminal does anyway, dropping the unfinished line
+                key @ (Key::Eof | Key::Ctrl('d')) if key == Key::Eof || line.chars.is_empty() => {
+                    output("\r\n")?;
+                    return Ok(0);
+                }
+                Key::Ctrl('d') | Key::Delete => line.delete(),
+                Key::Ctrl('c') => {
+                    down(&line)?;
+                    output("^C\r\n")?;
+                    return Err(Interrupted.into());
+                }
+                Key::Char(c) => line.insert(c.encode_utf8(&mut [0; 4])),
+                Key::Backspace | Key::Ctrl('h') => line.backspace(),
+                Key::Left | Key::Ctrl('b') => line.pos = line.pos.saturating_sub(1),
+                Key::Right | Key::Ctrl('f') => line.pos = (line.pos + 1).min(line.chars.len()),
+                Key::Home | Key::Ctrl('a') => line.pos = 0,
+                Key::End | Key::Ctrl('e') => line.pos = line.chars.len(),
+                Key::Ctrl('u') => {
+                    line.chars.drain(..line.pos);
+                    line.pos = 0;
+                }
+                Key::Ctrl('k') => line.chars.truncate(line.pos),
+                Key::Ctrl('w') => line.delete_word(),
+                Key::Ctrl('l') => {
+                    output("\x1b[H\x1b[2J")?;
+                    line.row = 0;
+                }
+                Key::Up | Key::Ctrl('p') if index > 0 => {
+                    if index == self.history.len() {
+                        saved = line.text();
+                    }
+                    index -= 1;
+                    line.set(&self.history[index]);
+                }
+                Key::Down | Key::Ctrl('n') if index < self.history.len() => {
+                    index += 1;
+                    match self.history.get(index) {
+                        Some(entry) => line.set(entry),
+                        None => line.set(&saved),
+                    }
+                }
+                Key::Tab => complete(&mut line, sh)?,
+                Key::Ctrl('r') if self.search(&mut line)? => {
+                    render(prompt, &mut line)?;
+                    break;
+                }
+                _ => {}
+            }
+
+            render(prompt, &mut line)?;
+        }
+
+        down(&line)?;
+        output("\r\n")?;
+        drop(raw);
+
+        let text = line.text();
+        buf.push_str(&text);
+        buf.push('\n');
+
+        Ok(text.len() + 1)
+    }
+
+    /// Searches history backwards while the query is typed, returns `true`
+    /// if the found line is accepted to run.
+    fn search(&self, line: &mut Buffer) -> Result<bool> {
+        let mut query = String::new();
+        let mut found: Option<usize> = None;
+
+        up(line)?;
+
+        loop {
+            // a multi-line entry is shown on the one line of the search
+            let entry = found.map_or(String::new(), |i| self.history[i].replace('\n', " "));
+            output(&format!(
+                "\r(reverse-i-search)`{}': {}\x1b[K\x1b[J",
+                query, entry
+            ))?;
+
+            match read_key()? {
+                Key::Char(c) => {
+                    query.push(c);
+                    let before = found.map_or(self.history.len(), |i| i + 1);
+                    found = self.find(&query, before);
+                }
+                Key::Backspace | Key::Ctrl('h') => {
+                    query.pop();
+                    found = self.find(&query, self.history.len());
+                }
+                Key::Ctrl('r') => {
+                    let before = found.unwrap_or(self.history.len());
+                    found = self.find(&query, before).or(found);
+                }
+                Key::Ctrl('g') | Key::Ctrl('c') => return Ok(false),
+                key => {
+                    if let Some(i) = found {
+                        line.set(&self.history[i]);
+                    }
+                    return Ok(key == Key::Enter);
+                }
+            }
+        }
+    }
+
+    /// Finds the latest entry containing the query before the position.
+    fn find(&self, query: &str, before: usize) -> Option<usize> {
+        if query.is_empty() {
+            return None;
+        }
+
+        (0..before).rev().find(|&i| self.history[i].contains(query))
+    }
+
+    /// Remembers the command, which may take several lines, and rewrites
+    /// the history file with the entries kept.
+    pub fn add(&mut self, text: &str) {
+        if text.trim().is_empty() || self.history.last().is_some_and(|last| last == text) {
+            return;
+        }
+
+        self.history.push(text.to_owned());
+        self.history
+            .drain(..self.history.len().saturating_sub(HISTORY_SIZE));
+
+        if let Some(file) = &self.file {
+            let _ = fs::write(file, save(&self.history));
+        }
+    }
+}
+
+/// Parses the history file, where a line ending with `\` goes on in the
+/// next one, as commands of several lines are saved.
+fn load(s: &str) -> Vec<String> {
+    let mut history = Vec::new();
+    let mut entry = String::new();
+
+    for line in s.lines() {
+        match line.strip_suffix('\\') {
+            Some(line) => {
+                entry.push_str(line);
+                entry.push('\n');
+            }
+            None => {
+                entry.push_str(line);
+                history.push(std::mem::take(&mut entry));
+            }
+        }
+    }
+    if !entry.is_empty() {
+        history.push(entry);
+    }
+
+    history
+}
+
+/// Formats the history file, see [`load`].
+fn save(history: &[String]) -> String {
+    history
+        .iter()
+        .map(|entry| format!("{}\n", entry.replace('\n', "\\\n")))
+        .collect()
+}
+
+/// Completes the word before the cursor, lists the candidates if there are
+/// several of them with nothing in common to add.
+fn complete(line: &mut Buffer, sh: &Shell) -> Result<()> {
+    let before = line.before();
+    let start = word_start(&before);
+    let word = unescape_word(&before[start..]);
+    let command = before[..start]
+        .trim_end()
+        .chars()
+        .last()
+        .map_or(true, |c| "|&;(".contains(c));
+
+    let found = candidates(&word, command, sh);
+    // the typed word is replaced, since it's escaped in its own way
+    let mut replace = |with: &str| {
+        let from = before[..start].chars().count();
+        line.chars.drain(from..line.pos);
+        line.pos = from;
+        line.insert(&escape_name(with));
+    };
+
+    match found.as_slice() {
+        [] => output("\x07")?,
+        [only] => {
+            replace(only);
+            if !only.ends_with('/') {
+                line.insert(" ");
+            }
+        }
+        [first, rest @ ..] => {
+            let common = rest.iter().fold(first.as_str(), |common, s| {
+                let len = common
+                    .char_indices()
+                    .zip(s.chars())
+                    .find(|((_, a), b)| a != b)
+                    .map_or(common.len().min(s.len()), |((pos, _), _)| pos);
+                &common[..len]
+            });
+
+            match common.len() > word.len() {
+                true => replace(common),
+                false => output(&format!("\r\n{}\r\n", found.join("  ")))?,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Start of the last word of the text, an escaped char doesn't end a word.
+fn word_start(text: &str) -> usize {
+    let mut start = 0;
+    let mut chars = text.char_indices();
+
+    while let Some((pos, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if c.is_whitespace() || "|&;<>(".contains(c) {
+            start = pos + c.len_utf8();
+        }
+    }
+
+    start
+}
+
+/// Redraws the prompt and the line placing the cursor, the rows of a
+/// multi-line command go on under the prompt.
+fn render(prompt: &str, line: &mut Buffer) -> Result<()> {
+    up(line)?;
+
+    let text = line.text().replace('\n', "\x1b[K\r\n");
+    let mut s = format!("\r{}{}\x1b[K\x1b[J", prompt, text);
+
+    let before = &line.chars[..line.pos];
+    let row = before.iter().filter(|&&c| c == '\n').count();
+    let column = match before.iter().rposition(|&c| c == '\n') {
+        Some(newline) => line.pos - newline - 1,
+        None => prompt.chars().count() + line.pos,
+    };
+    let rows = line.chars.iter().filter(|&&c| c == '\n').count();
+
+    if rows > row {
+        s.push_str(&format!("\x1b[{}A", rows - row));
+    }
+    s.push('\r');
+    if column > 0 {
+        s.push_str(&format!("\x1b[{}C", column));
+    }
+    line.row = row;
+
+    output(&s)
+}
+
+/// Moves the cursor up to the first row of the line.
+fn up(line: &mut Buffer) -> Result<()> {
+    if line.row > 0 {
+        output(&format!("\x1b[{}A", line.row))?;
+        line.row = 0;
+    }
+
+    Ok(())
+}
+
+/// Moves the cursor down to the last row of the line, output goes below
+/// it.
+fn down(line: &Buffer) -> Result<()> {
+    let rows = line.chars.iter().filter(|&&c| c == '\n').count();
+
+    if rows > line.row {
+        output(&format!("\x1b[{}B", rows - line.row))?;
+    }
+
+    Ok(())
+}
+
+fn output(s: &str) -> Result<()> {
+    let mut stderr = io::stderr();
+    stderr.write_all(s.as_bytes())?;
+    stderr.flush()?;
+
+    Ok(())
+}
+
+fn read_byte() -> Result<Option<u8>> {
+    let mut byte = [0];
+
+    loop {
+        match read(io::stdin().as_raw_fd(), &mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(Errno::EINTR) => {}
+            Err(why) => return Err(why.into()),
+        }
+    }
+}
+
+/// Reads a key from the terminal byte by byte, so that nothing typed ahead
+/// is taken away from the commands run next.
+fn read_key() -> Result<Key> {
+    let Some(byte) = read_byte()? else {
+        return Ok(Key::Eof);
+    };
+
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x7f => Key::Backspace,
+        0x1b => escape()?,
+        0x01..=0x1a => Key::Ctrl((b'a' + byte - 1) as char),
+        0x00..=0x1f => Key::Unknown,
+        _ => {
+            let len = match byte {
+                0xf0.. => 4,
+                0xe0.. => 3,
+                0xc0.. => 2,
+                _ => 1,
+            };
+            let mut bytes = vec![byte];
+
+            for _ in 1..len {
+                match read_byte()? {
+                    Some(byte) => bytes.push(byte),
+                    None => break,
+                }
+            }
+
+            match String::from_utf8(bytes).ok().and_then(|s| s.chars().next()) {
+                Some(c) => Key::Char(c),
+                None => Key::Unknown,
+            }
+        }
+    };
+
+    Ok(key)
+}
+
+/// Decodes the escape sequence of a special key.
+fn escape() -> Result<Key> {
+    let key = match read_byte()? {
+        Some(b'[') => match read_byte()? {
+            Some(b'A') => Key::Up,
+            Some(b'B') => Key::Down,
+            Some(b'C') => Key::Right,
+            Some(b'D') => Key::Left,
+            Some(b'H') => Key::Home,
+            Some(b'F') => Key::End,
+            Some(digit @ b'0'..=b'9') => {
+                let mut code = vec![digit];
+
+                while let Some(byte) = read_byte()? {
+                    if !byte.is_ascii_digit() {
+                        break;
+                    }
+                    code.push(byte);
+                }
+
+                match code.as_slice() {
+                    b"1" | b"7" => Key::Home,
+                    b"4" | b"8" => Key::End,
+                    b"3" => Key::Delete,
+                    _ => Key::Unknown,
+                }
+            }
+            _ => Key::Unknown,
+        },
+        Some(b'O') => match read_byte()? {
+            Some(b'H') => Key::Home,
+            Some(b'F') => Key::End,
+            _ => Key::Unknown,
+        },
+        _ => Key::Unknown,
+    };
+
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("history");
+        let mut editor = Editor::new(Some(file.clone()));
+
+        for i in 0..HISTORY_SIZE + 5 {
+            editor.add(&format!("echo {}", i));
+        }
+        editor.add("for i in 1 2\ndo echo $i\ndone");
+
+        // the file keeps the last entries only, rewritten on every command
+        let saved = fs::read_to_string(&file).unwrap();
+        assert_eq!(saved.lines().count(), HISTORY_SIZE + 2);
+        assert!(saved.starts_with("echo 6\n"));
+        assert!(saved.ends_with("for i in 1 2\\\ndo echo $i\\\ndone\n"));
+
+        let history = Editor::new(Some(file)).history;
+        assert_eq!(history.len(), HISTORY_SIZE);
+        assert_eq!(history[0], "echo 6");
+        assert_eq!(history[HISTORY_SIZE - 1], "for i in 1 2\ndo echo $i\ndone");
+    }
+}
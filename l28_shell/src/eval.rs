@@ -1,18 +1,39 @@
-use crate::cmd::Cmd;
+use crate::{
+    cmd::Cmd,
+    expand::{expand, expand_str},
+    job::{job_parser, signal_parser, Group, Job},
+    line::Line,
+    parser::{Redirect, Seg, Word},
+    ps::ps,
+    shell::Shell,
+    test::test,
+    vars::is_name,
+};
 use anyhow::{anyhow, bail, Result};
-use nix::sys::signal::kill;
+use nix::{
+    fcntl::{fcntl, FcntlArg, FdFlag},
+    sys::signal::{signal, SigHandler, Signal},
+    sys::wait::waitpid,
+    unistd::{self, dup2, fork, setpgid, ForkResult, Pid},
+};
 use std::{
-    borrow::Cow,
-    env,
-    io::{self, BufRead, BufReader, Write},
-    iter::{once, repeat},
+    collections::BTreeMap,
+    env, fmt,
+    fs::File,
+    io::{self, Read, Write},
+    iter::once,
+    mem,
+    os::{
+        fd::{AsFd, AsRawFd, OwnedFd, RawFd},
+        unix::process::CommandExt,
+    },
     path::{Path, PathBuf},
     process::{self, Command, Stdio},
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Expr {
-    Cmd(Cmd),
+    Cmd(Vec<Word>),
     Pipe {
         left: Box<Expr>,
         right: Box<Expr>,
@@ -21,123 +42,986 @@ pub enum Expr {
         left: Box<Expr>,
         right: Option<Box<Expr>>,
     },
+    Redirect {
+        expr: Box<Expr>,
+        redirects: Vec<Redirect>,
+    },
+    Seq {
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    And {
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    Or {
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    /// `( list )` run in a child process.
+    Subshell(Box<Expr>),
+    /// `{ list; }` run in the shell itself.
+    Group(Box<Expr>),
+    /// `name() body`, defines a function.
+    Function {
+        name: String,
+        body: Box<Expr>,
+    },
+    /// `if cond; then ...; else ...; fi`, `elif` is an `If` in `otherwise`.
+    If {
+        cond: Box<Expr>,
+        then: Box<Expr>,
+        otherwise: Option<Box<Expr>>,
+    },
+    /// `while cond; do body; done`
+    While {
+        cond: Box<Expr>,
+        body: Box<Expr>,
+    },
+    /// `for name in items; do body; done`, positional parameters without
+    /// items.
+    For {
+        name: String,
+        items: Option<Vec<Word>>,
+        body: Box<Expr>,
+    },
+}
+
+/// Most of nested function calls and aliases.
+const MAX_DEPTH: usize = 256;
+
+/// Unwinds evaluation up to the function being called.
+#[derive(Debug, thiserror::Error)]
+#[error("return: can only be used in a function")]
+struct Return(i32);
+
+/// Standard streams of a command, `None` inherits the shell's one.
+#[derive(Debug, Default)]
+pub struct Io {
+    pub stdin: Option<OwnedFd>,
+    pub stdout: Option<OwnedFd>,
+    pub stderr: Option<OwnedFd>,
+}
+
+impl Io {
+    fn stdin(&mut self) -> Stdio {
+        self.stdin.take().map_or_else(Stdio::inherit, Stdio::from)
+    }
+
+    fn stdout(&mut self) -> Stdio {
+        self.stdout.take().map_or_else(Stdio::inherit, Stdio::from)
+    }
+
+    fn stderr(&mut self) -> Stdio {
+        self.stderr.take().map_or_else(Stdio::inherit, Stdio::from)
+    }
+
+    fn output(&mut self) -> Box<dyn Write> {
+        match self.stdout.take() {
+            Some(fd) => Box::new(File::from(fd)),
+            None => Box::new(io::stdout()),
+        }
+    }
+
+    fn try_clone(&self) -> Result<Self> {
+        let clone = |fd: &Option<OwnedFd>| fd.as_ref().map(OwnedFd::try_clone).transpose();
+
+        Ok(Self {
+            stdin: clone(&self.stdin)?,
+            stdout: clone(&self.stdout)?,
+            stderr: clone(&self.stderr)?,
+        })
+    }
+
+    /// Applies redirections in the order they are written.
+    fn redirect(&mut self, redirects: &[Redirect], sh: &Shell) -> Result<()> {
+        for redirect in redirects {
+            match redirect {
+                Redirect::Read { fd, path } => {
+                    let path = expand_str(path, sh);
+                    let file = File::open(&path).map_err(|why| anyhow!("{}: {}", path, why))?;
+                    self.set(*fd, file.into())?;
+                }
+                Redirect::Write { fd, path, append } => {
+                    let path = expand_str(path, sh);
+                    let file = File::options()
+                        .create(true)
+                        .write(true)
+                        .append(*append)
+                        .truncate(!append)
+                        .open(&path)
+                        .map_err(|why| anyhow!("{}: {}", path, why))?;
+                    self.set(*fd, file.into())?;
+                }
+                Redirect::Dup { fd, to } => {
+                    let dup = self.dup(*to)?;
+                    self.set(*fd, dup)?;
+                }
+                Redirect::WriteAll { path } => {
+                    let path = expand_str(path, sh);
+                    let file = File::create(&path).map_err(|why| anyhow!("{}: {}", path, why))?;
+                    self.stderr = Some(file.try_clone()?.into());
+                    self.stdout = Some(file.into());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn set(&mut self, fd: RawFd, to: OwnedFd) -> Result<()> {
+        match fd {
+            0 => self.stdin = Some(to),
+            1 => self.stdout = Some(to),
+            2 => self.stderr = Some(to),
+            _ => bail!("{}: bad file descriptor", fd),
+        }
+
+        Ok(())
+    }
+
+    fn dup(&self, fd: RawFd) -> Result<OwnedFd> {
+        let dup = match fd {
+            0 => match &self.stdin {
+                Some(fd) => fd.try_clone()?,
+                None => io::stdin().as_fd().try_clone_to_owned()?,
+            },
+            1 => match &self.stdout {
+                Some(fd) => fd.try_clone()?,
+                None => io::stdout().as_fd().try_clone_to_owned()?,
+            },
+            2 => match &self.stderr {
+                Some(fd) => fd.try_clone()?,
+                None => io::stderr().as_fd().try_clone_to_owned()?,
+            },
+            _ => bail!("{}: bad file descriptor", fd),
+        };
+
+        Ok(dup)
+    }
+
+    /// Makes the streams standard ones of the current process.
+    fn install(self) -> Result<()> {
+        for (fd, std_fd) in [(self.stdin, 0), (self.stdout, 1), (self.stderr, 2)] {
+            if let Some(fd) = fd {
+                dup2(fd.as_raw_fd(), std_fd)?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl Expr {
-    pub fn evaluate(self, args: Option<String>) -> Result<String> {
-        let output = match self {
-            Expr::Cmd(cmd) => match cmd {
-                Cmd::Exec { comm, args: c_args } => {
-                    let output = exec(comm, c_args, args)?;
-                    println!("{}", output);
-                    process::exit(0);
-                }
-                Cmd::Exit => process::exit(0),
-                Cmd::Ext { comm, args: c_args } => exec(comm, c_args, args).map(Some)?,
-                Cmd::Kill { signal, pid } => kill(pid, signal).map(|_| None)?,
-                Cmd::Cd { path } => chdir(path).map(|_| None)?,
-                Cmd::Echo { string } => Some(string),
-                Cmd::Pwd => pwd().map(Some)?,
-                Cmd::Ps => ps().map(Some)?,
+    /// Evaluates the expression and returns its exit status, which is also
+    /// kept as the last one of the shell.
+    pub fn evaluate(self, mut io: Io, sh: &mut Shell) -> Result<i32> {
+        let checked = matches!(self, Expr::Cmd(_) | Expr::Pipe { .. } | Expr::Subshell(_));
+        let status = match self {
+            Expr::Cmd(words) => match command(&words, sh)? {
+                Simple::Assign(env) => {
+                    for (name, value) in env {
+                        sh.vars.set(&name, value);
+                    }
+                    0
+                }
+                Simple::Alias { expr, names } => nest(sh, |sh| {
+                    let len = sh.expanding.len();
+                    sh.expanding.extend(names);
+                    let result = expr.evaluate(io, sh);
+                    sh.expanding.truncate(len);
+                    result
+                })?,
+                Simple::Function { env, body, args } => call(body, env, args, io, sh)?,
+                Simple::Cmd { env, cmd } => {
+                    let error = io.dup(2)?;
+                    let failure = match cmd {
+                        Cmd::Ext { .. } => 127,
+                        _ => 1,
+                    };
+                    match run(cmd, env, io, sh) {
+                        Err(why) if why.is::<Return>() => return Err(why),
+                        result => result.unwrap_or_else(|why| {
+                            let _ = writeln!(File::from(error), "error: {}", why);
+                            failure
+                        }),
+                    }
+                }
             },
-            Expr::Pipe { left, right } => {
-                let mut output = left.evaluate(args)?;
-                let args = Some(output);
-                output = right.evaluate(args)?;
-                output.into()
+            expr @ (Expr::Pipe { .. } | Expr::Subshell(_)) => {
+                let job = expr.launch(io, sh, true)?;
+                sh.jobs.foreground(job)?
             }
-            Expr::Fork { left, right } => {
-                use nix::{
-                    sys::wait::waitpid,
-                    unistd::{fork, ForkResult},
+            Expr::Group(expr) => expr.evaluate(io, sh)?,
+            Expr::Function { name, body } => {
+                sh.functions.insert(name, *body);
+                0
+            }
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+            } => match report(condition(*cond, io.try_clone()?, sh), sh)? {
+                0 => then.evaluate(io, sh)?,
+                _ => match otherwise {
+                    Some(expr) => expr.evaluate(io, sh)?,
+                    None => 0,
+                },
+            },
+            Expr::While { cond, body } => {
+                let mut status = 0;
+
+                while report(condition(*cond.clone(), io.try_clone()?, sh), sh)? == 0 {
+                    status = report(body.clone().evaluate(io.try_clone()?, sh), sh)?;
+                    if interrupted(status) {
+                        break;
+                    }
+                }
+                status
+            }
+            Expr::For { name, items, body } => {
+                let items = match items {
+                    Some(words) => words.iter().flat_map(|word| expand(word, sh)).collect(),
+                    None => sh.args.clone(),
                 };
+                let mut status = 0;
+
+                for item in items {
+                    sh.vars.set(&name, item);
+                    status = report(body.clone().evaluate(io.try_clone()?, sh), sh)?;
+                    if interrupted(status) {
+                        break;
+                    }
+                }
+                status
+            }
+            Expr::Fork { left, right } => {
+                let job = left.launch(io.try_clone()?, sh, false)?;
+                sh.jobs.background(job);
+
+                match right {
+                    Some(expr) => expr.evaluate(io, sh)?,
+                    None => 0,
+                }
+            }
+            Expr::Redirect { expr, redirects } => {
+                io.redirect(&redirects, sh)?;
+                expr.evaluate(io, sh)?
+            }
+            Expr::Seq { left, right } => {
+                report(left.evaluate(io.try_clone()?, sh), sh)?;
+                right.evaluate(io, sh)?
+            }
+            Expr::And { left, right } => match report(condition(*left, io.try_clone()?, sh), sh)? {
+                0 => right.evaluate(io, sh)?,
+                status => status,
+            },
+            Expr::Or { left, right } => match report(condition(*left, io.try_clone()?, sh), sh)? {
+                0 => 0,
+                _ => right.evaluate(io, sh)?,
+            },
+        };
+
+        sh.status = status;
+
+        if checked && status != 0 && sh.errexit {
+            process::exit(status);
+        }
 
-                match unsafe { fork() } {
-                    Ok(ForkResult::Parent { child, .. }) => {
-                        println!("  + {}", child);
+        Ok(status)
+    }
+
+    /// Flattens a chain of pipes into its commands, left to right.
+    fn stages(self) -> Vec<Expr> {
+        let mut stages = vec![];
+        let mut next = self;
+
+        loop {
+            match next {
+                Expr::Pipe { left, right } => {
+                    stages.extend(left.stages());
+                    next = *right;
+                }
+                expr => {
+                    stages.push(expr);
+                    return stages;
+                }
+            }
+        }
+    }
 
-                        std::thread::spawn(move || {
-                            waitpid(child, None).unwrap();
-                            println!("  + {} done", child);
-                        });
+    /// Starts the expression as a job without waiting for it. Stages of a
+    /// pipeline are connected with pipes, so that all of them run
+    /// concurrently.
+    fn launch(self, mut io: Io, sh: &mut Shell, foreground: bool) -> Result<Job> {
+        let text = self.to_string();
+        let stages = self.stages();
+        let mut group = sh.jobs.group(foreground);
+        let mut pids = vec![];
+        let mut stdin = io.stdin.take();
+        let last = stages.len() - 1;
 
-                        match right {
-                            Some(expr) => expr.evaluate(args).map(Some),
-                            None => Ok(None),
+        for (i, stage) in stages.into_iter().enumerate() {
+            let (stdout, next) = if i == last {
+                (io.stdout.take(), None)
+            } else {
+                let (reader, writer) = pipe()?;
+                (Some(writer), Some(reader))
+            };
+            let stderr = io.dup(2)?;
+            let io = Io {
+                stdin: stdin.take(),
+                stdout,
+                stderr: Some(stderr.try_clone()?),
+            };
+
+            match stage.start(io, sh, group) {
+                Ok(pid) => {
+                    if let Some(group) = &mut group {
+                        if group.pgid.as_raw() == 0 {
+                            group.pgid = pid;
                         }
+                        // the child does the same, whoever is the first
+                        let _ = setpgid(pid, group.pgid);
                     }
-                    Ok(ForkResult::Child) => {
-                        let output = left.evaluate(args).map(Some)?;
-                        println!("{}", output.unwrap_or_default());
-                        process::exit(0);
+                    pids.push(Some(pid));
+                }
+                Err(why) => {
+                    writeln!(File::from(stderr), "error: {}", why)?;
+                    pids.push(None);
+                }
+            }
+
+            stdin = next;
+        }
+
+        Ok(Job::new(pids, group, text))
+    }
+
+    /// Starts the expression as a separate process without waiting for it.
+    fn start(self, mut io: Io, sh: &mut Shell, group: Option<Group>) -> Result<Pid> {
+        match self {
+            Expr::Cmd(words) => match command(&words, sh)? {
+                Simple::Cmd {
+                    env,
+                    cmd: Cmd::Ext { comm, args },
+                } => spawn(external(&comm, args, env, sh), io, group),
+                Simple::Cmd { env, cmd } => {
+                    fork_subshell(io, sh, group, |io, sh| run(cmd, env, io, sh))
+                }
+                Simple::Alias { expr, names } => {
+                    let len = sh.expanding.len();
+                    sh.expanding.extend(names);
+                    let result = expr.start(io, sh, group);
+                    sh.expanding.truncate(len);
+                    result
+                }
+                Simple::Function { env, body, args } => {
+                    fork_subshell(io, sh, group, |io, sh| call(body, env, args, io, sh))
+                }
+                Simple::Assign(_) => fork_subshell(io, sh, group, |_, _| Ok(0)),
+            },
+            Expr::Redirect { expr, redirects } => {
+                io.redirect(&redirects, sh)?;
+                expr.start(io, sh, group)
+            }
+            Expr::Subshell(expr) => fork_subshell(io, sh, group, |io, sh| expr.evaluate(io, sh)),
+            expr => fork_subshell(io, sh, group, |io, sh| expr.evaluate(io, sh)),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Cmd(words) => {
+                for (i, word) in words.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
                     }
-                    Err(why) => bail!("fork failed: {}", why),
-                }?
+                    write!(f, "{}", word)?;
+                }
+                Ok(())
             }
-        };
+            Expr::Pipe { left, right } => write!(f, "{} | {}", left, right),
+            Expr::Fork { left, right: None } => write!(f, "{} &", left),
+            Expr::Fork {
+                left,
+                right: Some(right),
+            } => write!(f, "{} & {}", left, right),
+            Expr::Redirect { expr, redirects } => {
+                write!(f, "{}", expr)?;
+                for redirect in redirects {
+                    write!(f, " {}", redirect)?;
+                }
+                Ok(())
+            }
+            Expr::Seq { left, right } => write!(f, "{}; {}", left, right),
+            Expr::And { left, right } => write!(f, "{} && {}", left, right),
+            Expr::Or { left, right } => write!(f, "{} || {}", left, right),
+            Expr::Subshell(expr) => write!(f, "({})", expr),
+            Expr::Group(expr) => write!(f, "{{ {}; }}", expr),
+            Expr::Function { name, body } => write!(f, "{}() {}", name, body),
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                write!(f, "if {}; then {}; ", cond, then)?;
+                match otherwise.as_deref() {
+                    Some(expr @ Expr::If { .. }) => write!(f, "el{}", expr),
+                    Some(expr) => write!(f, "else {}; fi", expr),
+                    None => write!(f, "fi"),
+                }
+            }
+            Expr::While { cond, body } => write!(f, "while {}; do {}; done", cond, body),
+            Expr::For { name, items, body } => {
+                write!(f, "for {}", name)?;
+                if let Some(items) = items {
+                    write!(f, " in")?;
+                    for item in items {
+                        write!(f, " {}", item)?;
+                    }
+                }
+                write!(f, "; do {}; done", body)
+            }
+        }
+    }
+}
 
-        Ok(output.unwrap_or_default())
+/// Command with its words expanded, resolved in the order: alias, function,
+/// builtin and external one.
+enum Simple {
+    /// Only assignments.
+    Assign(Vec<(String, String)>),
+    /// Alias replaced with its value and parsed anew, with names of the
+    /// aliases replaced.
+    Alias { expr: Expr, names: Vec<String> },
+    Function {
+        env: Vec<(String, String)>,
+        body: Expr,
+        args: Vec<String>,
+    },
+    /// Builtin or `Cmd::Ext`, with variables assigned in front of it.
+    Cmd {
+        env: Vec<(String, String)>,
+        cmd: Cmd,
+    },
+}
+
+fn command(words: &[Word], sh: &Shell) -> Result<Simple> {
+    if let Some((mut text, names)) = alias(words, sh) {
+        let expr = Expr::try_from(&Line::drain(&mut text))?;
+        return Ok(Simple::Alias { expr, names });
+    }
+
+    let mut env = vec![];
+    let mut words = words.iter();
+    let mut args = vec![];
+
+    for word in words.by_ref() {
+        match word.assignment() {
+            Some((name, value)) => env.push((name.to_owned(), expand_str(&value, sh))),
+            None => {
+                args.extend(expand(word, sh));
+                break;
+            }
+        }
+    }
+    args.extend(words.flat_map(|word| expand(word, sh)));
+
+    if sh.xtrace {
+        let assigned = env
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value));
+        let text = assigned.chain(args.iter().cloned()).collect::<Vec<_>>();
+        eprintln!("+ {}", text.join(" "));
+    }
+
+    if args.is_empty() {
+        return Ok(Simple::Assign(env));
+    }
+
+    if let Some(body) = sh.functions.get(&args[0]) {
+        return Ok(Simple::Function {
+            env,
+            body: body.clone(),
+            args: args.split_off(1),
+        });
+    }
+
+    Ok(Simple::Cmd {
+        env,
+        cmd: Cmd::from(args),
+    })
+}
+
+/// Replaces the alias starting the command with its value and returns the
+/// text to parse with the aliases replaced. Aliases in front of the value
+/// are replaced too, but none of them twice.
+fn alias(words: &[Word], sh: &Shell) -> Option<(String, Vec<String>)> {
+    let pos = words.iter().position(|word| word.assignment().is_none())?;
+    let [Seg::Bare(name)] = words[pos].0.as_slice() else {
+        return None;
+    };
+    if sh.expanding.contains(name) {
+        return None;
+    }
+    let mut value = sh.aliases.get(name)?.clone();
+    let mut seen = vec![name.as_str()];
+
+    loop {
+        let first = value.split_whitespace().next().unwrap_or_default();
+
+        if seen.contains(&first) || sh.expanding.iter().any(|name| name == first) {
+            // Escaped, so that it is not an alias anymore.
+            value = format!("\\{}", value.trim_start());
+            break;
+        }
+        match sh.aliases.get_key_value(first) {
+            Some((name, next)) => {
+                value = format!("{}{}", next, &value.trim_start()[first.len()..]);
+                seen.push(name);
+            }
+            None => break,
+        }
     }
+
+    let text = words[..pos]
+        .iter()
+        .map(Word::to_string)
+        .chain(once(value))
+        .chain(words[pos + 1..].iter().map(Word::to_string))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    Some((text, seen.into_iter().map(str::to_owned).collect()))
+}
+
+/// Runs `f` one level deeper into functions and aliases.
+fn nest(sh: &mut Shell, f: impl FnOnce(&mut Shell) -> Result<i32>) -> Result<i32> {
+    if sh.depth >= MAX_DEPTH {
+        bail!("maximum nesting level exceeded ({})", MAX_DEPTH);
+    }
+
+    sh.depth += 1;
+    let result = f(sh);
+    sh.depth -= 1;
+
+    result
 }
 
-fn ps() -> Result<String> {
-    let ps_output = Command::new("ps")
-        .args(["-o pid=", "-o comm=", "-o etime="])
-        .stdout(Stdio::piped())
-        .spawn()?
-        .stdout
-        .ok_or_else(|| anyhow!("failed to capture STDIO"))?;
+/// Calls the function with the arguments as positional parameters.
+fn call(
+    body: Expr,
+    env: Vec<(String, String)>,
+    args: Vec<String>,
+    io: Io,
+    sh: &mut Shell,
+) -> Result<i32> {
+    for (name, value) in env {
+        sh.vars.set(&name, value);
+    }
+
+    let args = mem::replace(&mut sh.args, args);
+    let result = nest(sh, |sh| body.evaluate(io, sh));
+    sh.args = args;
+
+    match result {
+        Err(why) => why.downcast::<Return>().map(|Return(status)| status),
+        result => result,
+    }
+}
+
+/// Prints the error of a failed evaluation and turns it into a status,
+/// `return` is passed on to the function.
+fn report(result: Result<i32>, sh: &mut Shell) -> Result<i32> {
+    match result {
+        Err(why) if why.is::<Return>() => Err(why),
+        result => Ok(result.unwrap_or_else(|why| {
+            eprintln!("error: {}", why);
+            if sh.errexit {
+                process::exit(1);
+            }
+            sh.status = 1;
+            1
+        })),
+    }
+}
+
+/// Evaluates the left side of `&&` or `||`, its failure doesn't make the
+/// shell exit under `set -e`.
+fn condition(expr: Expr, io: Io, sh: &mut Shell) -> Result<i32> {
+    let errexit = mem::replace(&mut sh.errexit, false);
+    let result = expr.evaluate(io, sh);
+    sh.errexit = errexit;
+
+    result
+}
+
+/// Checks whether the command was killed with `Ctrl-C`, which stops loops
+/// too.
+fn interrupted(status: i32) -> bool {
+    status == 128 + Signal::SIGINT as i32
+}
+
+/// Defines aliases given as `NAME=VALUE` and prints the others, or all of
+/// them if there are no arguments.
+fn alias_cmd(defs: Vec<String>, out: &mut dyn Write, sh: &mut Shell) -> Result<i32> {
+    let print = |out: &mut dyn Write, name: &str, value: &str| {
+        writeln!(out, "alias {}='{}'", name, value.replace('\'', "'\\''"))
+    };
+
+    if defs.is_empty() {
+        for (name, value) in &sh.aliases {
+            print(out, name, value)?;
+        }
+    }
+
+    let mut status = 0;
+
+    for def in defs {
+        match def.split_once('=') {
+            Some((name, value)) => {
+                sh.aliases.insert(name.to_owned(), value.to_owned());
+            }
+            None => match sh.aliases.get(&def) {
+                Some(value) => print(out, &def, value)?,
+                None => {
+                    eprintln!("error: alias: {}: not found", def);
+                    status = 1;
+                }
+            },
+        }
+    }
+
+    Ok(status)
+}
+
+/// Applies options of `set`, the first argument which is not an option
+/// starts new positional parameters.
+fn set(options: Vec<String>, sh: &mut Shell) -> Result<()> {
+    let mut options = options.into_iter();
+
+    while let Some(option) = options.next() {
+        let (on, flags) = match (option.strip_prefix('-'), option.strip_prefix('+')) {
+            (Some("-"), _) => {
+                sh.args = options.collect();
+                break;
+            }
+            (Some(flags), _) => (true, flags),
+            (_, Some(flags)) => (false, flags),
+            _ => {
+                sh.args = once(option).chain(options).collect();
+                break;
+            }
+        };
 
-    let reader = BufReader::new(ps_output);
+        for flag in flags.chars() {
+            match flag {
+                'e' => sh.errexit = on,
+                'x' => sh.xtrace = on,
+                _ => bail!("{}{}: invalid option", &option[..1], flag),
+            }
+        }
+    }
 
-    Ok(reader
-        .lines()
-        .map_while(|try_line| {
-            use std::fmt::Write;
+    Ok(())
+}
 
-            let mut line = try_line.ok()?;
-            let time_pos = 1 + line.rfind(' ')?;
-            let mls = {
-                let etime = &line[time_pos..];
-                let units = etime.split(['-', ':']).rev();
-                once(100)
-                    .chain(repeat(60))
-                    .zip(units)
-                    .try_fold(0, |mls, (mul, unit)| {
-                        unit.parse::<u32>().map(|unit| mul * unit + mls).ok()
-                    })?
+fn run(cmd: Cmd, env: Vec<(String, String)>, mut io: Io, sh: &mut Shell) -> Result<i32> {
+    let status = match cmd {
+        Cmd::Exec { comm, args } => {
+            let why = external(&comm, args, env, sh)
+                .stdin(io.stdin())
+                .stdout(io.stdout())
+                .stderr(io.stderr())
+                .exec();
+            bail!("{}: {}", comm, why);
+        }
+        Cmd::Exit { code } => process::exit(code.unwrap_or(sh.status)),
+        Cmd::Return { code } => return Err(Return(code.unwrap_or(sh.status)).into()),
+        Cmd::Ext { comm, args } => {
+            let text = once(&comm)
+                .chain(&args)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(" ");
+            let group = sh.jobs.group(true);
+            let pid = spawn(external(&comm, args, env, sh), io, group)?;
+            sh.jobs.foreground(Job::new(vec![Some(pid)], group, text))?
+        }
+        Cmd::Kill { args } => kill(args, &mut io.output(), sh)?,
+        Cmd::Test { args } => test_cmd(&args, io)?,
+        Cmd::Bracket { mut args } => match args.pop().as_deref() {
+            Some("]") => test_cmd(&args, io)?,
+            _ => {
+                writeln!(File::from(io.dup(2)?), "error: [: miss `]`")?;
+                2
+            }
+        },
+        Cmd::Cd { path } => {
+            let path = match path {
+                Some(path) => path,
+                None => sh
+                    .vars
+                    .get("HOME")
+                    .map(PathBuf::from)
+                    .ok_or(anyhow!("HOME not set"))?,
             };
-            line.truncate(time_pos);
-            write!(&mut line, "{}", mls).ok()?;
+            chdir(path)?;
+            sh.vars.set("PWD", pwd()?);
+            0
+        }
+        Cmd::Echo { string } => writeln!(io.output(), "{}", string).map(|_| 0)?,
+        Cmd::Pwd => writeln!(io.output(), "{}", pwd()?).map(|_| 0)?,
+        Cmd::Ps { all, columns } => ps(all, &columns, &mut io.output()).map(|_| 0)?,
+        Cmd::Export { vars } => {
+            if vars.is_empty() {
+                let mut out = io.output();
+                for (name, value) in sh.vars.exported() {
+                    writeln!(out, "export {}=\"{}\"", name, value)?;
+                }
+            }
+            for var in vars {
+                let (name, value) = match var.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_owned())),
+                    None => (var.as_str(), None),
+                };
+                if !is_name(name) {
+                    bail!("export: {}: not a valid identifier", name);
+                }
+                sh.vars.export(name, value);
+            }
+            0
+        }
+        Cmd::Unset { functions, names } => {
+            for name in names {
+                if functions {
+                    sh.functions.remove(&name);
+                } else {
+                    sh.vars.unset(&name);
+                }
+            }
+            0
+        }
+        Cmd::Alias { defs } => alias_cmd(defs, &mut io.output(), sh)?,
+        Cmd::Unalias { all, names } => {
+            if all {
+                sh.aliases.clear();
+            }
+            for name in names {
+                sh.aliases
+                    .remove(&name)
+                    .ok_or(anyhow!("unalias: {}: not found", name))?;
+            }
+            0
+        }
+        Cmd::Env => {
+            // prefix assignments go to the environment of this command only
+            let mut vars: BTreeMap<_, _> = sh.vars.exported().collect();
+            vars.extend(
+                env.iter()
+                    .map(|(name, value)| (name.as_str(), value.as_str())),
+            );
 
-            Some(line)
-        })
-        .fold(String::new(), |mut output, line| {
-            output.push_str(&line);
-            output.push('\n');
-            output
-        }))
+            let mut out = io.output();
+            for (name, value) in vars {
+                writeln!(out, "{}={}", name, value)?;
+            }
+            0
+        }
+        Cmd::Set { options } => set(options, sh).map(|_| 0)?,
+        Cmd::Jobs => sh.jobs.list(&mut io.output()).map(|_| 0)?,
+        Cmd::Fg { job } => sh.jobs.fg(job)?,
+        Cmd::Bg { job } => sh.jobs.bg(job)?,
+        Cmd::Wait { jobs } => sh.jobs.wait(jobs)?,
+    };
+
+    Ok(status)
 }
 
-fn exec(comm: String, args: Vec<String>, io_args: Option<String>) -> Result<String> {
-    let mut prc = Command::new(comm)
+/// Forks a child that runs `f` as [subshell], in `group` if any.
+fn fork_subshell(
+    io: Io,
+    sh: &mut Shell,
+    group: Option<Group>,
+    f: impl FnOnce(Io, &mut Shell) -> Result<i32>,
+) -> Result<Pid> {
+    match unsafe { fork() } {
+        Ok(ForkResult::Parent { child, .. }) => Ok(child),
+        Ok(ForkResult::Child) => {
+            if let Some(group) = group {
+                let _ = group.enter();
+            }
+            sh.jobs.release();
+            subshell(io, |io| f(io, sh))
+        }
+        Err(why) => bail!("fork failed: {}", why),
+    }
+}
+
+/// Opens a pipe whose ends, as the ones of `std`, are closed in executed
+/// commands, which get them as their standard streams only.
+fn pipe() -> Result<(OwnedFd, OwnedFd)> {
+    let (reader, writer) = unistd::pipe()?;
+
+    for fd in [&reader, &writer] {
+        fcntl(fd.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))?;
+    }
+
+    Ok((reader, writer))
+}
+
+/// Runs the commands in a subshell and returns their output without trailing
+/// newlines, as `$(...)` does.
+pub fn substitute(text: &str, sh: &Shell) -> Result<String> {
+    if text.trim().is_empty() {
+        return Ok(String::new());
+    }
+
+    let mut buf = text.to_owned();
+    let expr = Expr::try_from(&Line::drain(&mut buf))?;
+    let (reader, writer) = pipe()?;
+    let mut reader = File::from(reader);
+    let io = Io {
+        stdout: Some(writer),
+        ..Default::default()
+    };
+
+    let child = match unsafe { fork() } {
+        Ok(ForkResult::Parent { child, .. }) => child,
+        Ok(ForkResult::Child) => {
+            drop(reader);
+            let mut sh = sh.subshell();
+            subshell(io, |io| expr.evaluate(io, &mut sh))
+        }
+        Err(why) => bail!("fork failed: {}", why),
+    };
+    drop(io);
+
+    let mut output = String::new();
+    let read = reader.read_to_string(&mut output);
+    waitpid(child, None)?;
+    read?;
+
+    output.truncate(output.trim_end_matches('\n').len());
+
+    Ok(output)
+}
+
+/// Runs `f` in a forked child with `io` as its standard streams and exits
+/// with the returned status.
+fn subshell(io: Io, f: impl FnOnce(Io) -> Result<i32>) -> ! {
+    let status = unsafe { signal(Signal::SIGPIPE, SigHandler::SigDfl) }
+        .map_err(Into::into)
+        .and_then(|_| io.install())
+        .and_then(|_| f(Io::default()))
+        .unwrap_or_else(|why| {
+            eprintln!("error: {}", why);
+            1
+        });
+
+    let _ = io::stdout().flush();
+    process::exit(status);
+}
+
+/// Prepares an external command to run with exported variables and the
+/// assigned ones.
+fn external(comm: &str, args: Vec<String>, env: Vec<(String, String)>, sh: &Shell) -> Command {
+    let mut command = Command::new(comm);
+    command
         .args(args)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()?;
+        .env_clear()
+        .envs(sh.vars.exported())
+        .envs(env);
+    command
+}
+
+fn spawn(mut command: Command, mut io: Io, group: Option<Group>) -> Result<Pid> {
+    command
+        .stdin(io.stdin())
+        .stdout(io.stdout())
+        .stderr(io.stderr());
 
-    prc.stdin
-        .as_mut()
-        .unwrap()
-        .write_all(io_args.unwrap_or_default().as_bytes())?;
+    if let Some(group) = group {
+        unsafe { command.pre_exec(move || group.enter().map_err(io::Error::from)) };
+    }
 
-    let output = prc.wait_with_output()?;
+    let child = command.spawn().map_err(|why| {
+        if let Some(group) = group {
+            group.leave();
+        }
+        let comm = command.get_program().to_string_lossy();
+        match why.kind() {
+            io::ErrorKind::NotFound => anyhow!("{}: command not found", comm),
+            _ => anyhow!("{}: {}", comm, why),
+        }
+    })?;
 
-    if !output.status.success() {
-        bail!("{}", String::from_utf8_lossy(&output.stderr));
+    Ok(Pid::from_raw(child.id() as i32))
+}
+
+/// Sends a signal to processes and jobs, `kill [-s SIG | -SIG] TARGET...`,
+/// or lists signals with `kill -l`.
+fn kill(args: Vec<String>, out: &mut dyn Write, sh: &mut Shell) -> Result<i32> {
+    let mut args = args.into_iter().peekable();
+    let mut signal = Signal::SIGTERM;
+
+    match args.peek().map(String::as_str) {
+        Some("-l") => {
+            for signal in Signal::iterator() {
+                writeln!(out, "{:2}) {}", signal as i32, signal)?;
+            }
+            return Ok(0);
+        }
+        Some("-s") => {
+            args.next();
+            let name = args.next().ok_or(anyhow!("-s: miss signal"))?;
+            signal = signal_parser(&name).map_err(|why| anyhow!(why))?;
+        }
+        Some("--") => {
+            args.next();
+        }
+        Some(arg) if arg.len() > 1 && arg.starts_with('-') => {
+            signal = signal_parser(&arg[1..]).map_err(|why| anyhow!(why))?;
+            args.next();
+        }
+        _ => {}
+    }
+
+    if args.peek().is_none() {
+        bail!("usage: kill [-s SIGNAL | -SIGNAL] PID | %JOB ... or kill -l");
+    }
+
+    for target in args {
+        match target.starts_with('%') {
+            true => {
+                let id = job_parser(&target).map_err(|why| anyhow!(why))?;
+                sh.jobs.signal(id, signal)?;
+            }
+            false => {
+                let pid = target
+                    .parse()
+                    .map_err(|_| anyhow!("{}: arguments must be process or job IDs", target))?;
+                nix::sys::signal::kill(Pid::from_raw(pid), signal)
+                    .map_err(|why| anyhow!("({}) - {}", target, why.desc()))?;
+            }
+        }
     }
 
-    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    Ok(0)
+}
+
+/// Runs `test`, its errors make status 2 instead of 1 of false.
+fn test_cmd(args: &[String], io: Io) -> Result<i32> {
+    match test(args) {
+        Ok(result) => Ok(!result as i32),
+        Err(why) => {
+            writeln!(File::from(io.dup(2)?), "error: test: {}", why)?;
+            Ok(2)
+        }
+    }
 }
 
 fn pwd() -> Result<String, io::Error> {
@@ -145,14 +1029,11 @@ fn pwd() -> Result<String, io::Error> {
 }
 
 fn chdir(path: impl AsRef<Path>) -> Result<()> {
-    let target = match path.as_ref().to_str() {
-        Some("~") => env::var("HOME").map(PathBuf::from).map(Cow::Owned)?,
-        _ => path.as_ref().into(),
-    };
+    let path = path.as_ref();
 
-    if let Err(e) = env::set_current_dir(path.as_ref()) {
+    if let Err(e) = env::set_current_dir(path) {
         if let io::ErrorKind::NotFound = e.kind() {
-            bail!("{}: no such file or directory", target.to_string_lossy());
+            bail!("{}: no such file or directory", path.display());
         };
 
         return Err(anyhow!(e));
@@ -0,0 +1,517 @@
+use crate::{
+    eval::substitute,
+    glob::{escape, glob, is_pattern},
+    parser::{Seg, Word},
+    shell::Shell,
+};
+use std::mem;
+
+/// Expands the word into arguments. Braces and parameters are expanded in
+/// unquoted text, the latter in double quotes too, then the unquoted results
+/// are split on whitespace and patterns among them are replaced with the
+/// matching paths. Text in single quotes is taken as is.
+pub fn expand(word: &Word, sh: &Shell) -> Vec<String> {
+    let words = braces(word, 0).unwrap_or_else(|why| {
+        eprintln!("error: {}", why);
+        vec![word.clone()]
+    });
+
+    words
+        .iter()
+        .flat_map(|word| fields(word, sh, true))
+        .collect()
+}
+
+/// Expands the word into a single string without splitting and globbing, as
+/// for redirection targets and assigned values.
+pub fn expand_str(word: &Word, sh: &Shell) -> String {
+    fields(word, sh, false).join(" ")
+}
+
+/// Arguments being built from expanded pieces.
+#[derive(Default)]
+struct Fields {
+    done: Vec<String>,
+    cur: String,
+    /// The current argument as a glob pattern, with quoted text escaped.
+    pattern: String,
+    /// Quoted empty text still makes an argument.
+    started: bool,
+    /// Patterns are replaced with the matching paths.
+    glob: bool,
+}
+
+impl Fields {
+    fn push(&mut self, s: &str) {
+        self.cur.push_str(s);
+        self.pattern.push_str(s);
+        self.started |= !s.is_empty();
+    }
+
+    fn push_quoted(&mut self, s: &str) {
+        self.cur.push_str(s);
+        self.pattern.push_str(&escape(s));
+        self.started = true;
+    }
+
+    fn split(&mut self, s: &str) {
+        for (i, part) in s.split(char::is_whitespace).enumerate() {
+            if i > 0 {
+                self.end();
+            }
+            self.push(part);
+        }
+    }
+
+    /// Pushes quoted positional parameters, each of them makes an argument
+    /// of its own.
+    fn args(&mut self, args: &[String]) {
+        for (i, arg) in args.iter().enumerate() {
+            if i > 0 {
+                self.end();
+            }
+            self.push_quoted(arg);
+        }
+    }
+
+    fn end(&mut self) {
+        let pattern = mem::take(&mut self.pattern);
+        let cur = mem::take(&mut self.cur);
+
+        if !mem::take(&mut self.started) {
+            return;
+        }
+
+        // A pattern matching nothing is left as is.
+        let found = match self.glob && is_pattern(&pattern) {
+            true => glob(&pattern),
+            false => vec![],
+        };
+
+        match found.is_empty() {
+            true => self.done.push(cur),
+            false => self.done.extend(found),
+        }
+    }
+
+    fn finish(mut self) -> Vec<String> {
+        self.end();
+        self.done
+    }
+}
+
+enum Piece<'a> {
+    Lit(&'a str),
+    Value(String),
+    /// `$@`, kept apart to expand into separate arguments.
+    Args,
+}
+
+fn fields(word: &Word, sh: &Shell, split: bool) -> Vec<String> {
+    let mut fields = Fields {
+        glob: split,
+        ..Default::default()
+    };
+
+    for (i, seg) in word.0.iter().enumerate() {
+        match seg {
+            Seg::Bare(s) => {
+                let s = match (i, tilde(s, sh)) {
+                    (0, Some((home, rest))) => {
+                        fields.push_quoted(home);
+                        rest
+                    }
+                    _ => s.as_str(),
+                };
+                params(s, sh, |piece| match piece {
+                    Piece::Lit(s) => fields.push(s),
+                    Piece::Value(s) if split => fields.split(&s),
+                    Piece::Value(s) => fields.push(&s),
+                    Piece::Args if split => fields.split(&sh.args.join(" ")),
+                    Piece::Args => fields.push(&sh.args.join(" ")),
+                });
+            }
+            // "$@" of no parameters makes no argument at all.
+            Seg::Double(s) if sh.args.is_empty() && matches!(s.as_str(), "$@" | "${@}") => {}
+            Seg::Double(s) => {
+                fields.push_quoted("");
+                params(s, sh, |piece| match piece {
+                    Piece::Lit(s) => fields.push_quoted(s),
+                    Piece::Value(s) => fields.push_quoted(&s),
+                    Piece::Args if split => fields.args(&sh.args),
+                    Piece::Args => fields.push_quoted(&sh.args.join(" ")),
+                });
+            }
+            Seg::Single(s) => fields.push_quoted(s),
+        }
+    }
+
+    fields.finish()
+}
+
+/// Splits off leading `~` replaced with the home directory.
+fn tilde<'a, 'b>(s: &'a str, sh: &'b Shell) -> Option<(&'b str, &'a str)> {
+    let rest = s.strip_prefix('~')?;
+
+    match rest.is_empty() || rest.starts_with('/') {
+        true => Some((sh.vars.get("HOME")?, rest)),
+        false => None,
+    }
+}
+
+/// Most words brace expansion may make, so that a mistyped sequence doesn't
+/// hang the shell.
+const BRACE_LIMIT: usize = 100_000;
+
+/// Brace expansion makes more than [`BRACE_LIMIT`] words.
+#[derive(Debug, PartialEq, thiserror::Error)]
+#[error("brace expansion too large")]
+struct TooLarge;
+
+/// Expands braces in unquoted segments starting from the given one, the
+/// alternatives make words of their own.
+fn braces(word: &Word, from: usize) -> Result<Vec<Word>, TooLarge> {
+    for (i, seg) in word.0.iter().enumerate().skip(from) {
+        let Seg::Bare(s) = seg else {
+            continue;
+        };
+
+        if let Some(alts) = brace(s)? {
+            let mut words = vec![];
+
+            for alt in alts {
+                let mut word = word.clone();
+                word.0[i] = Seg::Bare(alt);
+                words.extend(braces(&word, i + 1)?);
+
+                if words.len() > BRACE_LIMIT {
+                    return Err(TooLarge);
+                }
+            }
+            return Ok(words);
+        }
+    }
+
+    Ok(vec![word.clone()])
+}
+
+/// Expands the first brace group of the text, `None` if there is none.
+fn brace(s: &str) -> Result<Option<Vec<String>>, TooLarge> {
+    let mut pos = 0;
+
+    while let Some(found) = s[pos..].find('{') {
+        let open = pos + found;
+
+        // Command substitutions are expanded on their own.
+        if let Some(subst) = s[pos..open].find("$(") {
+            let start = pos + subst + 1;
+            let Some(len) = closing_paren(&s[start..]) else {
+                return Ok(None);
+            };
+            pos = start + len;
+            continue;
+        }
+        let Some(close) = closing_brace(&s[open..]).map(|len| open + len) else {
+            return Ok(None);
+        };
+        pos = close;
+
+        // Parameters are not brace groups.
+        if s[..open].ends_with('$') {
+            continue;
+        }
+        let Some(alts) = alternatives(&s[open + 1..close])? else {
+            pos = open + 1;
+            continue;
+        };
+
+        let (prefix, suffix) = (&s[..open], &s[close + 1..]);
+        let suffixes = brace(suffix)?.unwrap_or_else(|| vec![suffix.to_owned()]);
+        let mut words = vec![];
+
+        for alt in alts {
+            for alt in brace(&alt)?.unwrap_or_else(|| vec![alt]) {
+                if words.len() + suffixes.len() > BRACE_LIMIT {
+                    return Err(TooLarge);
+                }
+                words.extend(
+                    suffixes
+                        .iter()
+                        .map(|suffix| format!("{}{}{}", prefix, alt, suffix)),
+                );
+            }
+        }
+        return Ok(Some(words));
+    }
+
+    Ok(None)
+}
+
+/// Parses the inside of braces: comma separated alternatives or a sequence
+/// of numbers or characters, `a..e`.
+fn alternatives(s: &str) -> Result<Option<Vec<String>>, TooLarge> {
+    let mut alts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, c) in s.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                alts.push(s[start..i].to_owned());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    if !alts.is_empty() {
+        alts.push(s[start..].to_owned());
+        return Ok(Some(alts));
+    }
+
+    let Some((from, to)) = s.split_once("..") else {
+        return Ok(None);
+    };
+
+    if let (Ok(from), Ok(to)) = (from.parse::<i64>(), to.parse::<i64>()) {
+        if from.abs_diff(to) >= BRACE_LIMIT as u64 {
+            return Err(TooLarge);
+        }
+        let seq: Vec<_> = match from <= to {
+            true => (from..=to).collect(),
+            false => (to..=from).rev().collect(),
+        };
+        return Ok(Some(seq.into_iter().map(|n| n.to_string()).collect()));
+    }
+
+    let mut from_chars = from.chars();
+    let mut to_chars = to.chars();
+
+    match (
+        from_chars.next(),
+        from_chars.next(),
+        to_chars.next(),
+        to_chars.next(),
+    ) {
+        (Some(from), None, Some(to), None) if from.is_ascii() && to.is_ascii() => {
+            let seq: Vec<_> = match from <= to {
+                true => (from..=to).collect(),
+                false => (to..=from).rev().collect(),
+            };
+            Ok(Some(seq.into_iter().map(String::from).collect()))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Walks the text passing literal parts and values of parameters to `f`.
+fn params<'a>(s: &'a str, sh: &Shell, mut f: impl FnMut(Piece<'a>)) {
+    let mut rest = s;
+
+    while let Some(pos) = rest.find('$') {
+        f(Piece::Lit(&rest[..pos]));
+        rest = &rest[pos + 1..];
+
+        if let Some(end) = rest.starts_with('(').then(|| closing_paren(rest)).flatten() {
+            let output = substitute(&rest[1..end], sh).unwrap_or_else(|why| {
+                eprintln!("error: {}", why);
+                String::new()
+            });
+            f(Piece::Value(output));
+            rest = &rest[end + 1..];
+            continue;
+        }
+
+        if let Some(len) = ["@", "{@}"]
+            .iter()
+            .find(|args| rest.starts_with(*args))
+            .map(|args| args.len())
+        {
+            f(Piece::Args);
+            rest = &rest[len..];
+            continue;
+        }
+
+        match parameter(rest, sh) {
+            Some((value, len)) => {
+                f(Piece::Value(value));
+                rest = &rest[len..];
+            }
+            None => f(Piece::Lit("$")),
+        }
+    }
+
+    f(Piece::Lit(rest));
+}
+
+/// Parses the parameter following `$` and returns its value along with the
+/// length of the parsed text.
+fn parameter(s: &str, sh: &Shell) -> Option<(String, usize)> {
+    if s.starts_with('{') {
+        let end = closing_brace(s)?;
+        let inner = &s[1..end];
+        let (name, op) = inner.split_at(name_len(inner, true));
+
+        if name.is_empty() {
+            return None;
+        }
+
+        let value = value(name, sh);
+        let value = match op {
+            "" => value.unwrap_or_default(),
+            op => {
+                let (colon, op) = match op.strip_prefix(':') {
+                    Some(op) => (true, op),
+                    None => (false, op),
+                };
+                let set = match colon {
+                    true => value.as_deref().is_some_and(|value| !value.is_empty()),
+                    false => value.is_some(),
+                };
+                let mut chars = op.chars();
+                let kind = chars.next()?;
+                let word = chars.as_str();
+
+                match kind {
+                    '-' if set => value.unwrap_or_default(),
+                    '-' => nested(word, sh),
+                    '+' if set => nested(word, sh),
+                    '+' => String::new(),
+                    _ => return None,
+                }
+            }
+        };
+
+        return Some((value, end + 1));
+    }
+
+    match name_len(s, false) {
+        0 => None,
+        len => Some((value(&s[..len], sh).unwrap_or_default(), len)),
+    }
+}
+
+/// Looks up a variable or a special parameter, `None` if it is unset.
+fn value(name: &str, sh: &Shell) -> Option<String> {
+    let value = match name {
+        "?" => sh.status.to_string(),
+        "$" => sh.pid.to_string(),
+        "#" => sh.args.len().to_string(),
+        "@" | "*" => sh.args.join(" "),
+        name => match name.parse::<usize>() {
+            Ok(0) => sh.name.clone(),
+            Ok(n) => sh.args.get(n - 1)?.clone(),
+            Err(_) => sh.vars.get(name)?.to_owned(),
+        },
+    };
+
+    Some(value)
+}
+
+/// Length of the parameter name at the start of the text, positional ones
+/// take more than one digit only in braces.
+fn name_len(s: &str, braced: bool) -> usize {
+    match s.chars().next() {
+        Some('?' | '$' | '#' | '@' | '*') => 1,
+        Some(c) if c.is_ascii_digit() && braced => {
+            s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len())
+        }
+        Some(c) if c.is_ascii_digit() => 1,
+        Some(c) if c == '_' || c.is_ascii_alphabetic() => s
+            .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+            .unwrap_or(s.len()),
+        _ => 0,
+    }
+}
+
+/// Finds the parenthesis closing the command substitution outside of quotes.
+fn closing_paren(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut chars = s.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None | Some('"'), '\\') => {
+                chars.next();
+            }
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') if depth == 1 => return Some(i),
+            (None, ')') => depth -= 1,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn closing_brace(s: &str) -> Option<usize> {
+    let mut depth = 0;
+
+    for (i, c) in s.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 1 => return Some(i),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Expands parameters of a word nested into `${...}`.
+fn nested(s: &str, sh: &Shell) -> String {
+    let mut value = String::new();
+
+    params(s, sh, |piece| match piece {
+        Piece::Lit(s) => value.push_str(s),
+        Piece::Value(s) => value.push_str(&s),
+        Piece::Args => value.push_str(&sh.args.join(" ")),
+    });
+
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brace_groups() {
+        assert_eq!(
+            brace("a{b,c{1,2}}d"),
+            Ok(Some(vec!["abd".into(), "ac1d".into(), "ac2d".into()]))
+        );
+        assert_eq!(
+            brace("{3..1}{a..b}"),
+            Ok(Some(
+                ["3a", "3b", "2a", "2b", "1a", "1b"]
+                    .map(String::from)
+                    .to_vec()
+            ))
+        );
+        assert_eq!(brace("${X}{}"), Ok(None));
+        assert_eq!(brace("x$(echo {a,b})"), Ok(None));
+        assert_eq!(
+            brace("$(echo {a,b}){c,d}"),
+            Ok(Some(vec!["$(echo {a,b})c".into(), "$(echo {a,b})d".into()]))
+        );
+    }
+
+    #[test]
+    fn brace_limit() {
+        assert_eq!(
+            brace("{1..100000}").map(|words| words.unwrap().len()),
+            Ok(100_000)
+        );
+        assert_eq!(brace("{1..999999999}"), Err(TooLarge));
+        assert_eq!(brace("{1..100}{1..100}{1..100}"), Err(TooLarge));
+        assert_eq!(brace("{a,{1..100}{1..100}{1..100}}"), Err(TooLarge));
+    }
+}
@@ -0,0 +1,257 @@
+use std::{fs, path::Path};
+
+/// Characters which make a pattern, a backslash escapes them.
+const MAGIC: &[char] = &['*', '?', '['];
+
+/// Checks whether the pattern has unescaped special characters.
+pub fn is_pattern(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            c if MAGIC.contains(&c) => return true,
+            _ => {}
+        }
+    }
+
+    false
+}
+
+/// Escapes special characters, so that the text matches itself only.
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        if c == '\\' || MAGIC.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+/// Finds paths matching the pattern, sorted. `**` as a whole component
+/// matches any number of directories.
+pub fn glob(pattern: &str) -> Vec<String> {
+    let (mut paths, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_owned()], rest),
+        None => (vec![String::new()], pattern),
+    };
+    let components: Vec<_> = rest.split('/').collect();
+
+    for (i, component) in components.iter().enumerate() {
+        let last = i == components.len() - 1;
+
+        paths = match *component {
+            "" => paths.into_iter().map(|path| join(&path, "")).collect(),
+            "**" => paths
+                .into_iter()
+                .flat_map(|path| {
+                    let mut found = match last {
+                        true => vec![],
+                        false => vec![path.clone()],
+                    };
+                    descend(&path, last, &mut found);
+                    found
+                })
+                .collect(),
+            component if is_pattern(component) => paths
+                .into_iter()
+                .flat_map(|path| {
+                    entries(&path)
+                        .into_iter()
+                        .filter(|name| visible(name, component) && matches(component, name))
+                        .map(move |name| join(&path, &name))
+                })
+                .collect(),
+            component => {
+                let name = unescape(component);
+                paths.into_iter().map(|path| join(&path, &name)).collect()
+            }
+        };
+
+        if !last {
+            paths.retain(|path| path.is_empty() || Path::new(path).is_dir());
+        }
+    }
+
+    paths.retain(|path| fs::symlink_metadata(path).is_ok());
+    paths.sort();
+
+    paths
+}
+
+fn join(dir: &str, name: &str) -> String {
+    match dir {
+        "" => name.to_owned(),
+        dir if dir.ends_with('/') => format!("{}{}", dir, name),
+        dir => format!("{}/{}", dir, name),
+    }
+}
+
+/// Names in the directory, the empty path stands for the current one.
+fn entries(dir: &str) -> Vec<String> {
+    let dir = if dir.is_empty() { "." } else { dir };
+
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Collects visible subdirectories of the directory recursively, or all
+/// entries if `files` is set.
+fn descend(dir: &str, files: bool, found: &mut Vec<String>) {
+    for name in entries(dir) {
+        if name.starts_with('.') {
+            continue;
+        }
+
+        let path = join(dir, &name);
+        let is_dir = fs::symlink_metadata(&path).is_ok_and(|meta| meta.is_dir());
+
+        if is_dir || files {
+            found.push(path.clone());
+        }
+        if is_dir {
+            descend(&path, files, found);
+        }
+    }
+}
+
+/// Hidden files are matched only by a pattern starting with a dot.
+fn visible(name: &str, pattern: &str) -> bool {
+    !name.starts_with('.') || pattern.starts_with('.')
+}
+
+/// Matches the name against the pattern of `*`, `?` and `[...]`.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<_> = pattern.chars().collect();
+    let name: Vec<_> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Pattern after the last `*` and where in the name it was tried, only
+    // the last star is backtracked to, as it may take any of the text.
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        // length of the matching pattern item
+        let len = match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                star = Some((p, n));
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match class(&pattern[p + 1..], Some(&name[n])) {
+                Some((true, len)) => Some(len + 1),
+                Some((false, _)) => None,
+                // Unclosed bracket is an ordinary character.
+                None => (name[n] == '[').then_some(1),
+            },
+            Some('\\') if p + 1 < pattern.len() => (name[n] == pattern[p + 1]).then_some(2),
+            Some(&c) => (name[n] == c).then_some(1),
+            None => None,
+        };
+
+        match (len, star) {
+            (Some(len), _) => {
+                p += len;
+                n += 1;
+            }
+            (None, Some((after, tried))) => {
+                p = after;
+                n = tried + 1;
+                star = Some((after, n));
+            }
+            (None, None) => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Matches the character against the bracket expression following `[`,
+/// returns the result and the length of the expression with `]`.
+fn class(pattern: &[char], c: Option<&char>) -> Option<(bool, usize)> {
+    let negate = matches!(pattern.first(), Some('!' | '^'));
+    let mut i = negate as usize;
+    let mut found = false;
+
+    // `]` right after the opening is a member of the set.
+    let start = i;
+    while i < pattern.len() && (pattern[i] != ']' || i == start) {
+        let lo = pattern[i];
+        let (hi, len) = match (pattern.get(i + 1), pattern.get(i + 2)) {
+            (Some('-'), Some(&hi)) if hi != ']' => (hi, 3),
+            _ => (lo, 1),
+        };
+
+        found |= c.is_some_and(|&c| lo <= c && c <= hi);
+        i += len;
+    }
+
+    (i < pattern.len()).then_some((found != negate, i + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "main.rc"));
+        assert!(matches("m??n.*", "main.rs"));
+        assert!(matches("*", ""));
+        assert!(matches("a*b*c", "aXbYbc"));
+        assert!(matches("*a*", "bab"));
+        assert!(!matches("a*b", "ab/c"));
+        assert!(matches("a\\*", "a*"));
+    }
+
+    #[test]
+    fn many_stars() {
+        let name = "a".repeat(100);
+
+        assert!(!matches("*a*a*a*a*a*a*a*a*a*a*b", &name));
+        assert!(matches("*a*a*a*a*a*a*a*a*a*a*", &name));
+    }
+
+    #[test]
+    fn brackets() {
+        assert!(matches("[abc].txt", "b.txt"));
+        assert!(matches("[a-c]*", "cat"));
+        assert!(!matches("[!a-c]*", "cat"));
+        assert!(matches("[]x]", "]"));
+        assert!(matches("[a", "[a"));
+    }
+
+    #[test]
+    fn escaped() {
+        assert!(matches(&escape("*?"), "*?"));
+        assert!(!matches(&escape("*"), "a"));
+        assert!(!is_pattern(&escape("[x]")));
+    }
+}
@@ -0,0 +1,425 @@
+use anyhow::{anyhow, bail, Result};
+use nix::{
+    errno::Errno,
+    sys::{
+        signal::{kill, killpg, sigaction, signal, SaFlags, SigAction, SigHandler, SigSet, Signal},
+        wait::{waitpid, WaitPidFlag, WaitStatus},
+    },
+    unistd::{getpgrp, getpid, setpgid, tcsetpgrp, Pid},
+};
+use std::{
+    collections::BTreeMap,
+    ffi::c_int,
+    fmt,
+    io::{self, Write},
+    os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+/// Process group of the foreground job, the target of forwarded signals.
+static FOREGROUND: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn forward(signal: c_int) {
+    let pgid = FOREGROUND.load(Ordering::Relaxed);
+
+    if let (true, Ok(signal)) = (pgid > 0, Signal::try_from(signal)) {
+        let _ = killpg(Pid::from_raw(pgid), signal);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    Running,
+    Stopped,
+    Done(i32),
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            State::Running => write!(f, "Running"),
+            State::Stopped => write!(f, "Stopped"),
+            State::Done(0) => write!(f, "Done"),
+            State::Done(status) => write!(f, "Exit {}", status),
+        }
+    }
+}
+
+/// Process group a started process joins, exists only under job control.
+#[derive(Debug, Clone, Copy)]
+pub struct Group {
+    /// Zero makes the process a leader of a new group.
+    pub pgid: Pid,
+    /// Terminal to take, set for foreground jobs.
+    tty: Option<RawFd>,
+}
+
+impl Group {
+    /// Puts the calling child process into the group and restores signals
+    /// the shell handles itself.
+    pub fn enter(&self) -> nix::Result<()> {
+        setpgid(Pid::from_raw(0), self.pgid)?;
+
+        if let Some(tty) = self.tty {
+            tcsetpgrp(unsafe { BorrowedFd::borrow_raw(tty) }, getpgrp())?;
+        }
+
+        for sig in [
+            Signal::SIGINT,
+            Signal::SIGTSTP,
+            Signal::SIGTTIN,
+            Signal::SIGTTOU,
+        ] {
+            unsafe { signal(sig, SigHandler::SigDfl) }?;
+        }
+
+        Ok(())
+    }
+
+    /// Takes the terminal back after a process failed to start, since its
+    /// child has handed the terminal over to the group before `exec`.
+    pub fn leave(&self) {
+        if let Some(tty) = self.tty {
+            let _ = tcsetpgrp(unsafe { BorrowedFd::borrow_raw(tty) }, getpgrp());
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Job {
+    /// `None` unless the processes were put into a group of their own.
+    pgid: Option<Pid>,
+    /// Processes which are not reaped yet.
+    pids: Vec<Pid>,
+    /// Process which gives the job its status.
+    last: Option<Pid>,
+    status: i32,
+    state: State,
+    text: String,
+}
+
+impl Job {
+    /// Creates a job of the started processes, `None` stands for a process
+    /// which failed to start.
+    pub fn new(pids: Vec<Option<Pid>>, group: Option<Group>, text: String) -> Self {
+        let last = pids.last().copied().flatten();
+        let pids: Vec<_> = pids.into_iter().flatten().collect();
+
+        Self {
+            pgid: group.and(pids.first().copied()),
+            status: if last.is_some() { 0 } else { 127 },
+            state: if pids.is_empty() {
+                State::Done(127)
+            } else {
+                State::Running
+            },
+            last,
+            pids,
+            text,
+        }
+    }
+
+    fn signal(&self, signal: Signal) -> Result<()> {
+        match self.pgid {
+            Some(pgid) => killpg(pgid, signal)?,
+            None => {
+                for &pid in &self.pids {
+                    kill(pid, signal)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reaps processes until all of them exit or one stops, stops early with
+    /// `WNOHANG` if some are still running.
+    fn wait(&mut self, flags: Option<WaitPidFlag>) -> Result<()> {
+        while let Some(&pid) = self.pids.first() {
+            match waitpid(pid, flags) {
+                Ok(WaitStatus::Exited(_, code)) => self.exit(pid, code),
+                Ok(WaitStatus::Signaled(_, signal, _)) => self.exit(pid, 128 + signal as i32),
+                Ok(WaitStatus::Stopped(..)) => {
+                    self.state = State::Stopped;
+                    return Ok(());
+                }
+                Ok(WaitStatus::Continued(_)) => self.state = State::Running,
+                Ok(WaitStatus::StillAlive) => return Ok(()),
+                Ok(_) => {}
+                Err(Errno::ECHILD) => self.exit(pid, 0),
+                Err(Errno::EINTR) => {}
+                Err(why) => return Err(why.into()),
+            }
+        }
+
+        self.state = State::Done(self.status);
+
+        Ok(())
+    }
+
+    fn exit(&mut self, pid: Pid, status: i32) {
+        self.pids.retain(|&p| p != pid);
+
+        if self.last == Some(pid) {
+            self.status = status;
+        }
+    }
+}
+
+/// Table of background and stopped jobs.
+#[derive(Debug, Default)]
+pub struct Jobs {
+    /// Terminal handed over to foreground jobs, set only under job control.
+    tty: Option<OwnedFd>,
+    table: BTreeMap<usize, Job>,
+}
+
+impl Jobs {
+    /// Turns job control on: the shell leads its own process group, owns the
+    /// terminal and forwards interrupts to the foreground job.
+    pub fn control(&mut self) -> Result<()> {
+        let tty = io::stdin().as_fd().try_clone_to_owned()?;
+        let shell = getpid();
+
+        if getpgrp() != shell {
+            setpgid(shell, shell)?;
+        }
+        tcsetpgrp(&tty, shell)?;
+
+        let action = SigAction::new(
+            SigHandler::Handler(forward),
+            SaFlags::SA_RESTART,
+            SigSet::empty(),
+        );
+        for sig in [Signal::SIGINT, Signal::SIGTSTP] {
+            unsafe { sigaction(sig, &action) }?;
+        }
+        for sig in [Signal::SIGTTIN, Signal::SIGTTOU] {
+            unsafe { signal(sig, SigHandler::SigIgn) }?;
+        }
+
+        self.tty = Some(tty);
+
+        Ok(())
+    }
+
+    /// Forgets about jobs of the parent shell in a forked child.
+    pub fn release(&mut self) {
+        self.tty = None;
+        self.table.clear();
+    }
+
+    /// Returns the group for processes of a new job.
+    pub fn group(&self, foreground: bool) -> Option<Group> {
+        let tty = self.tty.as_ref()?;
+
+        Some(Group {
+            pgid: Pid::from_raw(0),
+            tty: foreground.then(|| tty.as_raw_fd()),
+        })
+    }
+
+    /// Waits for the job holding the terminal until it exits or stops, the
+    /// stopped one goes to the table.
+    pub fn foreground(&mut self, mut job: Job) -> Result<i32> {
+        if let (Some(tty), Some(pgid)) = (&self.tty, job.pgid) {
+            let _ = tcsetpgrp(tty, pgid);
+            FOREGROUND.store(pgid.as_raw(), Ordering::Relaxed);
+        }
+
+        let waited = job.wait(Some(WaitPidFlag::WUNTRACED));
+
+        FOREGROUND.store(0, Ordering::Relaxed);
+        if let Some(tty) = &self.tty {
+            tcsetpgrp(tty, getpgrp())?;
+        }
+        waited?;
+
+        match job.state {
+            State::Stopped => {
+                let text = job.text.clone();
+                let id = self.insert(job);
+                eprintln!("\n[{}]+  Stopped\t{}", id, text);
+                Ok(128 + Signal::SIGTSTP as i32)
+            }
+            _ => Ok(job.status),
+        }
+    }
+
+    /// Puts the running job to the table and reports its number.
+    pub fn background(&mut self, job: Job) {
+        let pid = job.pids.last().copied();
+        let id = self.insert(job);
+
+        match pid {
+            Some(pid) => eprintln!("[{}] {}", id, pid),
+            None => eprintln!("[{}]", id),
+        }
+    }
+
+    fn insert(&mut self, job: Job) -> usize {
+        let id = self.table.keys().next_back().map_or(1, |id| id + 1);
+        self.table.insert(id, job);
+        id
+    }
+
+    /// Resolves a job number, the most recent job is the default one.
+    fn id(&self, id: Option<usize>) -> Result<usize> {
+        match id {
+            Some(id) if self.table.contains_key(&id) => Ok(id),
+            Some(id) => bail!("%{}: no such job", id),
+            None => self
+                .table
+                .keys()
+                .next_back()
+                .copied()
+                .ok_or(anyhow!("no current job")),
+        }
+    }
+
+    /// Checks for finished or stopped jobs without blocking and reports them.
+    pub fn reap(&mut self) {
+        let mut done = vec![];
+
+        for (&id, job) in self.table.iter_mut() {
+            let state = job.state;
+
+            if let Err(why) = job.wait(Some(
+                WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED | WaitPidFlag::WCONTINUED,
+            )) {
+                eprintln!("error: %{}: {}", id, why);
+            }
+
+            match job.state {
+                State::Done(_) => done.push(id),
+                State::Stopped if state != State::Stopped => {
+                    eprintln!("[{}]+  Stopped\t{}", id, job.text)
+                }
+                _ => {}
+            }
+        }
+
+        for id in done {
+            let job = self.table.remove(&id).unwrap();
+            eprintln!("[{}]+  {}\t{}", id, job.state, job.text);
+        }
+    }
+
+    pub fn list(&mut self, out: &mut dyn Write) -> Result<()> {
+        self.reap();
+
+        let current = self.table.keys().next_back().copied();
+        let previous = self.table.keys().nth_back(1).copied();
+
+        for (&id, job) in &self.table {
+            let mark = match Some(id) {
+                id if id == current => '+',
+                id if id == previous => '-',
+                _ => ' ',
+            };
+            writeln!(out, "[{}]{}  {}\t{}", id, mark, job.state, job.text)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends the signal to the job, a stopped one is continued to get it.
+    pub fn signal(&mut self, id: usize, signal: Signal) -> Result<()> {
+        let id = self.id(Some(id))?;
+        let job = self.table.get_mut(&id).unwrap();
+
+        job.signal(signal)?;
+        if job.state == State::Stopped && matches!(signal, Signal::SIGTERM | Signal::SIGHUP) {
+            job.signal(Signal::SIGCONT)?;
+            job.state = State::Running;
+        } else if signal == Signal::SIGCONT {
+            job.state = State::Running;
+        }
+
+        Ok(())
+    }
+
+    /// Continues the job in the foreground and waits for it.
+    pub fn fg(&mut self, id: Option<usize>) -> Result<i32> {
+        let id = self.id(id)?;
+        let mut job = self.table.remove(&id).unwrap();
+
+        println!("{}", job.text);
+
+        if let (Some(tty), Some(pgid)) = (&self.tty, job.pgid) {
+            tcsetpgrp(tty, pgid)?;
+        }
+        job.signal(Signal::SIGCONT)?;
+        job.state = State::Running;
+
+        self.foreground(job)
+    }
+
+    /// Continues the stopped job in the background.
+    pub fn bg(&mut self, id: Option<usize>) -> Result<i32> {
+        let id = self.id(id)?;
+        let job = self.table.get_mut(&id).unwrap();
+
+        job.signal(Signal::SIGCONT)?;
+        job.state = State::Running;
+        println!("[{}]+ {} &", id, job.text);
+
+        Ok(0)
+    }
+
+    /// Waits for the given jobs, or all of them, to finish and returns the
+    /// status of the last one, a stopped job is reported and stays in the
+    /// table.
+    pub fn wait(&mut self, ids: Vec<usize>) -> Result<i32> {
+        let ids: Vec<_> = match ids.is_empty() {
+            true => self.table.keys().copied().collect(),
+            false => ids
+                .into_iter()
+                .map(|id| self.id(Some(id)))
+                .collect::<Result<_>>()?,
+        };
+        let mut status = 0;
+
+        for id in ids {
+            let job = self.table.get_mut(&id).unwrap();
+
+            // a stopped job never exits by itself
+            if job.state != State::Stopped {
+                job.wait(Some(WaitPidFlag::WUNTRACED))?;
+            }
+
+            if job.state == State::Stopped {
+                eprintln!("[{}]+  Stopped\t{}", id, job.text);
+                status = 128 + Signal::SIGTSTP as i32;
+            } else {
+                status = self.table.remove(&id).unwrap().status;
+            }
+        }
+
+        Ok(status)
+    }
+}
+
+/// Parses `%N` into a job number.
+pub fn job_parser(s: &str) -> Result<usize, String> {
+    s.strip_prefix('%')
+        .unwrap_or(s)
+        .parse()
+        .map_err(|_| format!("{}: no such job", s))
+}
+
+/// Parses a signal given by number or by name, `SIG` may be omitted.
+pub fn signal_parser(s: &str) -> Result<Signal, String> {
+    let signal = match s.parse::<i32>() {
+        Ok(number) => Signal::try_from(number).ok(),
+        Err(_) => {
+            let name = s.to_uppercase();
+            match name.starts_with("SIG") {
+                true => name.parse().ok(),
+                false => format!("SIG{}", name).parse().ok(),
+            }
+        }
+    };
+
+    signal.ok_or_else(|| format!("{}: invalid signal", s))
+}
@@ -1,42 +1,232 @@
 mod cmd;
+mod complete;
+mod editor;
 mod eval;
+mod expand;
+mod glob;
+mod job;
 mod line;
 mod parser;
+mod ps;
+mod shell;
+mod test;
+mod vars;
 
 use anyhow::{anyhow, Result};
-use std::io;
+use clap::Parser;
+use std::{
+    env,
+    fs::File,
+    io::{self, BufRead, BufReader, Cursor, IsTerminal},
+    path::{Path, PathBuf},
+    process,
+};
 
-use crate::{eval::Expr, line::Line};
+use crate::{
+    editor::{Editor, Interrupted},
+    eval::{Expr, Io},
+    line::Line,
+    parser::Incomplete,
+    shell::Shell,
+};
+
+#[derive(Parser)]
+struct Args {
+    /// выполнить команды из строки
+    #[clap(short = 'c')]
+    command: Option<String>,
+
+    /// файл скрипта, с `-c` - значение `$0`
+    script: Option<String>,
+
+    /// позиционные параметры `$1`, `$2`, ...
+    #[clap(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
+}
+
+/// Source of command lines.
+enum Input {
+    /// Interactive terminal.
+    Editor(Editor),
+    Reader(Box<dyn BufRead>),
+}
+
+impl Input {
+    /// Reads a line into the buffer, `None` at the end of input.
+    fn read_line<'a>(
+        &mut self,
+        prompt: &str,
+        buf: &'a mut String,
+        sh: &Shell,
+    ) -> Result<Option<Line<'a>>> {
+        let read = match self {
+            Input::Editor(editor) => editor.read_line(prompt, buf, sh)?,
+            Input::Reader(reader) => reader.read_line(buf)?,
+        };
+
+        Ok((read > 0).then(|| Line::drain(buf)))
+    }
+
+    /// Adds the complete command to the history of the editor.
+    fn add(&mut self, text: &str) {
+        if let Input::Editor(editor) = self {
+            editor.add(text);
+        }
+    }
+}
 
 fn main() {
+    let args = Args::parse();
+    let mut sh = Shell::new();
+
+    let mut input = match input(args, &mut sh) {
+        Ok(input) => input,
+        Err(why) => {
+            eprintln!("error: {}", why);
+            process::exit(127);
+        }
+    };
+
+    if let Input::Editor(_) = input {
+        rc(&mut sh);
+    }
+    run(&mut input, &mut sh);
+
+    process::exit(sh.status);
+}
+
+/// Runs commands of the input until its end.
+fn run(input: &mut Input, sh: &mut Shell) {
     let mut buf = String::new();
 
     loop {
-        if let Err(why) = repl(&mut buf) {
-            println!("error: {}", why);
+        sh.jobs.reap();
+
+        match repl(&mut buf, input, sh) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(why) => {
+                eprintln!("error: {}", why);
+                sh.status = 1;
+                if sh.errexit {
+                    break;
+                }
+            }
         }
     }
 }
 
-fn repl(buf: &mut String) -> Result<()> {
-    let line = &read_line(buf)?;
+/// Runs `~/.l28shellrc` of an interactive shell, if there is one.
+fn rc(sh: &mut Shell) {
+    let Some(home) = sh.vars.get("HOME") else {
+        return;
+    };
+    let path = PathBuf::from(home).join(".l28shellrc");
+
+    match File::open(&path) {
+        Ok(file) => run(&mut Input::Reader(Box::new(BufReader::new(file))), sh),
+        Err(why) if why.kind() == io::ErrorKind::NotFound => {}
+        Err(why) => eprintln!("error: {}: {}", path.display(), why),
+    }
+}
+
+/// Picks the source of commands: the string of `-c`, the script, or stdin,
+/// which is edited interactively and gets job control if it is a terminal.
+fn input(args: Args, sh: &mut Shell) -> Result<Input> {
+    sh.args = args.args;
+
+    if let Some(command) = args.command {
+        if let Some(name) = args.script {
+            sh.name = name;
+        }
+        return Ok(Input::Reader(Box::new(Cursor::new(command.into_bytes()))));
+    }
 
-    if line.is_empty() {
-        return Ok(());
+    if let Some(script) = args.script {
+        let file = File::open(&script).map_err(|why| anyhow!("{}: {}", script, why))?;
+        sh.name = script;
+        return Ok(Input::Reader(Box::new(BufReader::new(file))));
     }
 
-    let output = Expr::try_from(line)
-        .map_err(|_| anyhow!("command not found"))?
-        .evaluate(None)?;
+    if !io::stdin().is_terminal() {
+        return Ok(Input::Reader(Box::new(io::stdin().lock())));
+    }
+
+    if let Err(why) = sh.jobs.control() {
+        eprintln!("error: no job control: {}", why);
+    }
+
+    Ok(Input::Editor(Editor::new(history_file(sh))))
+}
+
+/// Runs the next command, which may take several lines, returns `false` at
+/// the end of input.
+fn repl(buf: &mut String, input: &mut Input, sh: &mut Shell) -> Result<bool> {
+    let mut text = String::new();
+
+    let expr = loop {
+        let prompt = match text.is_empty() {
+            true => prompt(sh),
+            false => CONTINUATION.to_owned(),
+        };
+        let line = match input.read_line(&prompt, buf, sh) {
+            Ok(Some(line)) => line,
+            Ok(None) if text.is_empty() => return Ok(false),
+            Ok(None) => return Err(Incomplete.into()),
+            Err(why) if why.is::<Interrupted>() => return Ok(true),
+            Err(why) => return Err(why),
+        };
+
+        if text.is_empty() && (line.is_empty() || line.starts_with('#')) {
+            return Ok(true);
+        }
+
+        text.push_str(&line);
+        text.push('\n');
 
-    println!("{}", output);
+        match Expr::try_from(&Line::drain(&mut text.clone())) {
+            Err(why) if why.is::<Incomplete>() => continue,
+            expr => {
+                input.add(text.trim_end());
+                break expr?;
+            }
+        }
+    };
 
-    Ok(())
+    expr.evaluate(Io::default(), sh)?;
+
+    Ok(true)
 }
 
-fn read_line(buf: &mut String) -> Result<Line<'_>> {
-    io::stdin().read_line(buf)?;
-    let line = Line::drain(buf);
+/// Prompt for the next line of an unfinished command.
+const CONTINUATION: &str = "> ";
+
+/// Shows the current directory, with home shortened to `~`, and the status
+/// of the last command.
+fn prompt(sh: &Shell) -> String {
+    let cwd = env::current_dir()
+        .map(|cwd| cwd.display().to_string())
+        .unwrap_or_else(|_| "?".to_owned());
+    // compared by components, so that `/home/user2` isn't under `/home/user`
+    let rest = sh
+        .vars
+        .get("HOME")
+        .filter(|home| !home.is_empty())
+        .and_then(|home| Path::new(&cwd).strip_prefix(home).ok());
+    let cwd = match rest {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_owned(),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => cwd,
+    };
 
-    Ok(line)
+    format!("{} [{}]$ ", cwd, sh.status)
+}
+
+/// History is kept in `$HISTFILE`, `~/.l28_shell_history` by default.
+fn history_file(sh: &Shell) -> Option<PathBuf> {
+    match (sh.vars.get("HISTFILE"), sh.vars.get("HOME")) {
+        (Some(file), _) => Some(file.into()),
+        (None, Some(home)) => Some(PathBuf::from(home).join(".l28_shell_history")),
+        _ => None,
+    }
 }
@@ -1,21 +1,226 @@
-use crate::{
-    cmd::{Cli, Cmd},
-    eval::Expr,
-    line::Line,
-};
-use anyhow::{anyhow, Error, Result};
-use clap::Parser;
-
-#[derive(Debug)]
+use crate::{eval::Expr, line::Line, vars::is_name};
+use anyhow::{anyhow, bail, Error, Result};
+use std::{fmt, iter::Peekable, mem, os::fd::RawFd, str::Chars};
+
+#[derive(Debug, PartialEq)]
 pub enum Redir {
     Pipe,
     Fork,
+    Seq,
+    And,
+    Or,
+}
+
+impl fmt::Display for Redir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Redir::Pipe => "|",
+            Redir::Fork => "&",
+            Redir::Seq => ";",
+            Redir::And => "&&",
+            Redir::Or => "||",
+        };
+
+        write!(f, "{}", op)
+    }
+}
+
+/// Piece of a word, the way it was quoted decides which expansions apply.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Seg {
+    /// Unquoted text.
+    Bare(String),
+    /// Text in double quotes.
+    Double(String),
+    /// Text in single quotes or escaped with a backslash, taken literally.
+    Single(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Word(pub Vec<Seg>);
+
+impl Word {
+    fn push(&mut self, seg: Seg) {
+        match (self.0.last_mut(), seg) {
+            (Some(Seg::Bare(s)), Seg::Bare(t))
+            | (Some(Seg::Double(s)), Seg::Double(t))
+            | (Some(Seg::Single(s)), Seg::Single(t)) => s.push_str(&t),
+            (_, seg) => self.0.push(seg),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Splits `NAME=value` into the name and the value word.
+    pub fn assignment(&self) -> Option<(&str, Word)> {
+        let (first, rest) = self.0.split_first()?;
+        let (name, value) = match first {
+            Seg::Bare(s) => s.split_once('=')?,
+            _ => return None,
+        };
+
+        if !is_name(name) {
+            return None;
+        }
+
+        let mut word = Word(vec![Seg::Bare(value.to_owned())]);
+        word.0.extend(rest.iter().cloned());
+
+        Some((name, word))
+    }
+}
+
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for seg in &self.0 {
+            match seg {
+                Seg::Bare(s) => write!(f, "{}", s)?,
+                Seg::Double(s) => write!(f, "\"{}\"", s)?,
+                // a quote can't be escaped inside quotes, so it's closed around it
+                Seg::Single(s) => write!(f, "'{}'", s.replace('\'', r"'\''"))?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Redirect {
+    /// `[n]< path`
+    Read { fd: RawFd, path: Word },
+    /// `[n]> path` and `[n]>> path`
+    Write { fd: RawFd, path: Word, append: bool },
+    /// `[n]>&m`
+    Dup { fd: RawFd, to: RawFd },
+    /// `&> path`
+    WriteAll { path: Word },
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Redirect::Read { fd: 0, path } => write!(f, "< {}", path),
+            Redirect::Read { fd, path } => write!(f, "{}< {}", fd, path),
+            Redirect::Write { fd, path, append } => {
+                if *fd != 1 {
+                    write!(f, "{}", fd)?;
+                }
+                write!(f, "{} {}", if *append { ">>" } else { ">" }, path)
+            }
+            Redirect::Dup { fd, to } => write!(f, "{}>&{}", fd, to),
+            Redirect::WriteAll { path } => write!(f, "&> {}", path),
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum Token {
-    Cmd(Cmd),
+    Cmd(Vec<Word>),
+    Redir(Redir),
+    Redirect(Redirect),
+    /// `(` opening a subshell.
+    Open,
+    /// `)` closing a subshell.
+    Close,
+    /// `{` opening a group, only as the first word of a command.
+    Begin,
+    /// `}` closing a group, likewise.
+    End,
+    Keyword(Keyword),
+}
+
+/// Shell text of the token, the way it's shown in errors.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Cmd(words) => {
+                for (i, word) in words.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", word)?;
+                }
+                Ok(())
+            }
+            Token::Redir(redir) => write!(f, "{}", redir),
+            Token::Redirect(redirect) => write!(f, "{}", redirect),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+            Token::Begin => write!(f, "{{"),
+            Token::End => write!(f, "}}"),
+            Token::Keyword(keyword) => write!(f, "{}", keyword.as_str()),
+        }
+    }
+}
+
+/// Reserved word of a compound command, recognized only where a command
+/// starts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Keyword {
+    If,
+    Then,
+    Elif,
+    Else,
+    Fi,
+    While,
+    For,
+    Do,
+    Done,
+}
+
+impl Keyword {
+    const ALL: [Keyword; 9] = [
+        Keyword::If,
+        Keyword::Then,
+        Keyword::Elif,
+        Keyword::Else,
+        Keyword::Fi,
+        Keyword::While,
+        Keyword::For,
+        Keyword::Do,
+        Keyword::Done,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Keyword::If => "if",
+            Keyword::Then => "then",
+            Keyword::Elif => "elif",
+            Keyword::Else => "else",
+            Keyword::Fi => "fi",
+            Keyword::While => "while",
+            Keyword::For => "for",
+            Keyword::Do => "do",
+            Keyword::Done => "done",
+        }
+    }
+}
+
+/// Input ends in the middle of a command, more lines may complete it.
+#[derive(Debug, thiserror::Error)]
+#[error("syntax error: unexpected end of input")]
+pub struct Incomplete;
+
+/// Unquoted operator met by the tokenizer.
+#[derive(Debug, PartialEq)]
+enum Op {
     Redir(Redir),
+    Read(RawFd),
+    Write(RawFd, bool),
+    Dup(RawFd, RawFd),
+    WriteAll,
+    Open,
+    Close,
+    Newline,
+}
+
+#[derive(Debug, PartialEq)]
+enum Lexeme {
+    Word(Word),
+    Op(Op),
 }
 
 impl<'a> TryFrom<&Line<'a>> for Expr {
@@ -27,11 +232,13 @@ impl<'a> TryFrom<&Line<'a>> for Expr {
             vec.reverse();
             vec
         };
-        let mut into = vec![];
 
-        parse_in_place(&mut from, &mut into)?;
+        let expr = list(&mut from)?;
 
-        Ok(into.pop().unwrap())
+        match from.last() {
+            None => Ok(expr),
+            Some(token) => bail!("syntax error near `{}`", token),
+        }
     }
 }
 
@@ -40,83 +247,879 @@ impl<'a> TryFrom<&Line<'a>> for Vec<Token> {
 
     fn try_from(line: &Line<'a>) -> Result<Self, Self::Error> {
         let mut tokens = vec![];
-        let args = shlex::split(line).ok_or(anyhow!("command not found"))?;
-        let mut buf = args.as_slice();
-
-        while !buf.is_empty() {
-            let cmd = {
-                let cmd = {
-                    let pos = buf
-                        .iter()
-                        .position(|arg| ["|", "&"].contains(&arg.as_str()))
-                        .unwrap_or(buf.len());
-                    let (cmd, suf) = buf.split_at(pos);
-                    buf = suf;
-                    cmd
-                };
-                Cli::try_parse_from(cmd).map(|cli| cli.cmd).unwrap_or({
-                    let comm = cmd[0].to_owned();
-                    let args = cmd[1..].iter().map(String::to_owned).collect();
-                    Cmd::Ext { comm, args }
-                })
+        let mut lexemes = split(line)?.into_iter();
+        let mut words = vec![];
+        let mut redirects = vec![];
+
+        while let Some(lexeme) = lexemes.next() {
+            let token = match lexeme {
+                Lexeme::Word(word) => match reserved(&word) {
+                    // Braces of a function body follow `function name` too.
+                    Some(token)
+                        if words.is_empty()
+                            || matches!(words.as_slice(), [keyword, _] if is_function(keyword)) =>
+                    {
+                        token
+                    }
+                    _ => {
+                        words.push(word);
+                        continue;
+                    }
+                },
+                Lexeme::Op(Op::Redir(redir)) => Token::Redir(redir),
+                Lexeme::Op(Op::Open) => Token::Open,
+                Lexeme::Op(Op::Close) => Token::Close,
+                // Separates commands like `;`, unless there is nothing to
+                // separate yet.
+                Lexeme::Op(Op::Newline) => {
+                    flush(&mut tokens, &mut words, &mut redirects)?;
+                    if ends_command(&tokens) {
+                        tokens.push(Token::Redir(Redir::Seq));
+                    }
+                    continue;
+                }
+                Lexeme::Op(Op::Dup(fd, to)) => {
+                    redirects.push(Redirect::Dup { fd, to });
+                    continue;
+                }
+                Lexeme::Op(op) => {
+                    let path = match lexemes.next() {
+                        Some(Lexeme::Word(path)) => path,
+                        _ => bail!("miss redirection target"),
+                    };
+                    redirects.push(match op {
+                        Op::Read(fd) => Redirect::Read { fd, path },
+                        Op::Write(fd, append) => Redirect::Write { fd, path, append },
+                        Op::WriteAll => Redirect::WriteAll { path },
+                        Op::Dup(..) | Op::Redir(_) | Op::Open | Op::Close | Op::Newline => {
+                            unreachable!()
+                        }
+                    });
+                    continue;
+                }
             };
 
-            tokens.push(Token::Cmd(cmd));
+            flush(&mut tokens, &mut words, &mut redirects)?;
 
-            match buf.split_first() {
-                Some((redir, suf)) => {
-                    match redir.as_str() {
-                        "|" => tokens.push(Token::Redir(Redir::Pipe)),
-                        "&" => tokens.push(Token::Redir(Redir::Fork)),
-                        _ => {}
-                    }
-                    buf = suf;
+            if let Token::Redir(_) = token {
+                if !ends_command(&tokens) {
+                    bail!("syntax error: miss command");
                 }
-                None => break,
             }
+            tokens.push(token);
         }
 
+        flush(&mut tokens, &mut words, &mut redirects)?;
+
         Ok(tokens)
     }
 }
 
-fn parse_in_place(from: &mut Vec<Token>, into: &mut Vec<Expr>) -> Result<()> {
-    loop {
-        let token = match from.pop() {
-            None => return Ok(()),
-            Some(token) => token,
+/// Turns collected words into a command followed by its redirections, which
+/// may also follow a compound command.
+fn flush(
+    tokens: &mut Vec<Token>,
+    words: &mut Vec<Word>,
+    redirects: &mut Vec<Redirect>,
+) -> Result<()> {
+    if !words.is_empty() {
+        tokens.push(Token::Cmd(mem::take(words)));
+    } else if !redirects.is_empty() && !closes(tokens.last()) {
+        bail!("syntax error: miss command");
+    }
+    tokens.extend(redirects.drain(..).map(Token::Redirect));
+
+    Ok(())
+}
+
+/// Checks whether the tokens end with a command, which an operator may
+/// follow. `()` is the header of a function.
+fn ends_command(tokens: &[Token]) -> bool {
+    match tokens {
+        [.., Token::Open, Token::Close] => false,
+        [.., Token::Cmd(_) | Token::Redirect(_)] => true,
+        [.., token] => closes(Some(token)),
+        [] => false,
+    }
+}
+
+/// Closing brackets and keywords of compound commands.
+fn closes(token: Option<&Token>) -> bool {
+    matches!(
+        token,
+        Some(Token::Close | Token::End | Token::Keyword(Keyword::Fi | Keyword::Done))
+    )
+}
+
+/// Recognizes unquoted braces of a group and keywords.
+fn reserved(word: &Word) -> Option<Token> {
+    let [Seg::Bare(s)] = word.0.as_slice() else {
+        return None;
+    };
+
+    match s.as_str() {
+        "{" => Some(Token::Begin),
+        "}" => Some(Token::End),
+        s => Keyword::ALL
+            .into_iter()
+            .find(|keyword| keyword.as_str() == s)
+            .map(Token::Keyword),
+    }
+}
+
+/// Splits the line into words and operators, the latter are recognized only
+/// outside of quotes.
+fn split(line: &str) -> Result<Vec<Lexeme>> {
+    let mut lexemes = vec![];
+    let mut word = Word::default();
+    let mut chars = line.chars().peekable();
+
+    let flush = |word: &mut Word, lexemes: &mut Vec<Lexeme>| {
+        if !word.is_empty() {
+            lexemes.push(Lexeme::Word(mem::take(word)));
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        let op = match c {
+            '\'' => {
+                word.push(Seg::Single(String::new()));
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(Seg::Single(c.into())),
+                        None => return Err(Incomplete.into()),
+                    }
+                }
+                continue;
+            }
+            '"' => {
+                word.push(Seg::Double(String::new()));
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(Seg::Single(c.into())),
+                            Some(c) => word.push(Seg::Double(['\\', c].iter().collect())),
+                            None => return Err(Incomplete.into()),
+                        },
+                        Some('$') if chars.peek() == Some(&'(') => {
+                            let mut sub = String::from('$');
+                            substitution(&mut chars, &mut sub)?;
+                            word.push(Seg::Double(sub));
+                        }
+                        Some(c) => word.push(Seg::Double(c.into())),
+                        None => return Err(Incomplete.into()),
+                    }
+                }
+                continue;
+            }
+            '\\' => {
+                match chars.next() {
+                    // Joins the next line.
+                    Some('\n') => {}
+                    Some(c) => word.push(Seg::Single(c.into())),
+                    None => return Err(Incomplete.into()),
+                }
+                continue;
+            }
+            '\n' => Op::Newline,
+            c if c.is_whitespace() => {
+                flush(&mut word, &mut lexemes);
+                continue;
+            }
+            '#' if word.is_empty() => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+                continue;
+            }
+            ';' => Op::Redir(Redir::Seq),
+            '(' => Op::Open,
+            ')' => Op::Close,
+            '|' if chars.next_if_eq(&'|').is_some() => Op::Redir(Redir::Or),
+            '|' => Op::Redir(Redir::Pipe),
+            '&' if chars.next_if_eq(&'&').is_some() => Op::Redir(Redir::And),
+            '&' if chars.next_if_eq(&'>').is_some() => Op::WriteAll,
+            '&' => Op::Redir(Redir::Fork),
+            '<' | '>' => {
+                let fd = match word.0.as_slice() {
+                    [Seg::Bare(fd)] if fd.len() == 1 => fd.parse::<RawFd>().ok(),
+                    _ => None,
+                };
+                if fd.is_some() {
+                    word = Word::default();
+                }
+                flush(&mut word, &mut lexemes);
+
+                if c == '<' {
+                    Op::Read(fd.unwrap_or(0))
+                } else if chars.next_if_eq(&'>').is_some() {
+                    Op::Write(fd.unwrap_or(1), true)
+                } else if chars.next_if_eq(&'&').is_some() {
+                    let to = chars
+                        .next_if(char::is_ascii_digit)
+                        .and_then(|c| c.to_digit(10))
+                        .ok_or(anyhow!("miss redirection target"))?;
+                    Op::Dup(fd.unwrap_or(1), to as RawFd)
+                } else {
+                    Op::Write(fd.unwrap_or(1), false)
+                }
+            }
+            '$' if chars.peek() == Some(&'(') => {
+                let mut sub = String::from(c);
+                substitution(&mut chars, &mut sub)?;
+                word.push(Seg::Bare(sub));
+                continue;
+            }
+            '$' if chars.peek() == Some(&'{') => {
+                let mut param = String::from(c);
+                let mut depth = 0;
+                loop {
+                    match chars.next() {
+                        Some(c) => {
+                            param.push(c);
+                            match c {
+                                '{' => depth += 1,
+                                '}' if depth == 1 => break,
+                                '}' => depth -= 1,
+                                _ => {}
+                            }
+                        }
+                        None => bail!("bad substitution: {}", param),
+                    }
+                }
+                word.push(Seg::Bare(param));
+                continue;
+            }
+            c => {
+                word.push(Seg::Bare(c.into()));
+                continue;
+            }
         };
 
-        let expr = match token {
-            Token::Cmd(cmd) => Expr::Cmd(cmd),
-            Token::Redir(redir) => match redir {
-                Redir::Pipe => {
-                    let left = into
-                        .pop()
-                        .map(Box::new)
-                        .ok_or(anyhow!("miss left pipe argument"))?;
-                    let right = {
-                        parse_in_place(from, into)?;
-                        into.pop()
-                            .map(Box::new)
-                            .ok_or(anyhow!("miss right pipe argument"))?
-                    };
-                    Expr::Pipe { left, right }
-                }
-                Redir::Fork => {
-                    let left = into
-                        .pop()
-                        .map(Box::new)
-                        .ok_or(anyhow!("miss left pipe argument"))?;
-                    let right = {
-                        parse_in_place(from, into)?;
-                        into.pop().map(Box::new)
-                    };
-                    Expr::Fork { left, right }
+        flush(&mut word, &mut lexemes);
+        lexemes.push(Lexeme::Op(op));
+    }
+
+    flush(&mut word, &mut lexemes);
+
+    Ok(lexemes)
+}
+
+/// Reads `(...)` of a command substitution as is, up to the matching
+/// parenthesis outside of quotes.
+fn substitution(chars: &mut Peekable<Chars>, out: &mut String) -> Result<()> {
+    let mut depth = 0;
+    let mut quote = None;
+
+    while let Some(c) = chars.next() {
+        out.push(c);
+
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None | Some('"'), '\\') => out.extend(chars.next()),
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Err(Incomplete.into())
+}
+
+// The functions below take tokens in reverse order, so that the next one is
+// at the end of `from`. Precedence grows from lists to single commands.
+
+/// `and_or ((';' | '&') and_or)*`
+fn list(from: &mut Vec<Token>) -> Result<Expr> {
+    let left = and_or(from)?;
+
+    let expr = match from.last() {
+        None => left,
+        Some(_) if ends(from) => left,
+        Some(Token::Redir(Redir::Seq)) => {
+            from.pop();
+            match ends(from) {
+                true => left,
+                false => Expr::Seq {
+                    left: Box::new(left),
+                    right: Box::new(list(from)?),
+                },
+            }
+        }
+        Some(Token::Redir(Redir::Fork)) => {
+            from.pop();
+            Expr::Fork {
+                left: Box::new(left),
+                right: match ends(from) {
+                    true => None,
+                    false => Some(Box::new(list(from)?)),
+                },
+            }
+        }
+        Some(token) => bail!("syntax error near `{}`", token),
+    };
+
+    Ok(expr)
+}
+
+/// Checks whether the list ends here, at the end of the line, a bracket or
+/// a keyword other than the opening ones.
+fn ends(from: &[Token]) -> bool {
+    match from.last() {
+        None | Some(Token::Close | Token::End) => true,
+        Some(Token::Keyword(keyword)) => {
+            !matches!(keyword, Keyword::If | Keyword::While | Keyword::For)
+        }
+        _ => false,
+    }
+}
+
+/// `pipeline (('&&' | '||') pipeline)*`
+fn and_or(from: &mut Vec<Token>) -> Result<Expr> {
+    let mut left = pipeline(from)?;
+
+    loop {
+        left = match from.last() {
+            Some(Token::Redir(Redir::And)) => {
+                from.pop();
+                Expr::And {
+                    left: Box::new(left),
+                    right: Box::new(pipeline(from)?),
+                }
+            }
+            Some(Token::Redir(Redir::Or)) => {
+                from.pop();
+                Expr::Or {
+                    left: Box::new(left),
+                    right: Box::new(pipeline(from)?),
                 }
-            },
+            }
+            _ => return Ok(left),
+        };
+    }
+}
+
+/// `command ('|' command)*`
+fn pipeline(from: &mut Vec<Token>) -> Result<Expr> {
+    let left = command(from)?;
+
+    match from.last() {
+        Some(Token::Redir(Redir::Pipe)) => {
+            from.pop();
+            Ok(Expr::Pipe {
+                left: Box::new(left),
+                right: Box::new(pipeline(from)?),
+            })
+        }
+        _ => Ok(left),
+    }
+}
+
+/// `(words | '(' list ')' | '{' list '}' | if | while | for) redirect*
+/// | name '(' ')' command`
+fn command(from: &mut Vec<Token>) -> Result<Expr> {
+    let expr = match from.pop() {
+        Some(Token::Cmd(words)) => match function(&words, from) {
+            Some(name) => {
+                return Ok(Expr::Function {
+                    name,
+                    body: Box::new(command(from)?),
+                })
+            }
+            None => Expr::Cmd(words),
+        },
+        Some(Token::Open) => Expr::Subshell(Box::new(inner(from, Token::Close, ")")?)),
+        Some(Token::Begin) => Expr::Group(Box::new(inner(from, Token::End, "}")?)),
+        Some(Token::Keyword(Keyword::If)) => if_clause(from)?,
+        Some(Token::Keyword(Keyword::While)) => {
+            let cond = list(from)?;
+            expect(from, Keyword::Do)?;
+            Expr::While {
+                cond: Box::new(cond),
+                body: Box::new(body(from)?),
+            }
+        }
+        Some(Token::Keyword(Keyword::For)) => for_clause(from)?,
+        Some(Token::Keyword(keyword)) => bail!("syntax error near `{}`", keyword.as_str()),
+        None => return Err(Incomplete.into()),
+        _ => bail!("syntax error: miss command"),
+    };
+    let mut redirects = vec![];
+
+    while let Some(Token::Redirect(_)) = from.last() {
+        if let Some(Token::Redirect(redirect)) = from.pop() {
+            redirects.push(redirect);
+        }
+    }
+
+    if redirects.is_empty() {
+        return Ok(expr);
+    }
+
+    Ok(Expr::Redirect {
+        expr: Box::new(expr),
+        redirects,
+    })
+}
+
+/// Recognizes the header of a function definition, `name ()` or
+/// `function name [()]`, and takes its parentheses.
+fn function(words: &[Word], from: &mut Vec<Token>) -> Option<String> {
+    let name = match words {
+        [name] => name,
+        [keyword, name] if is_function(keyword) => name,
+        _ => return None,
+    };
+    let [Seg::Bare(name)] = name.0.as_slice() else {
+        return None;
+    };
+
+    match from.len().checked_sub(2).map(|i| &from[i..]) {
+        Some([Token::Close, Token::Open]) => {
+            from.truncate(from.len() - 2);
+        }
+        _ if words.len() == 1 => return None,
+        _ => {}
+    }
+
+    Some(name.clone())
+}
+
+fn is_function(word: &Word) -> bool {
+    matches!(word.0.as_slice(), [Seg::Bare(s)] if s == "function")
+}
+
+/// List inside brackets up to the closing one.
+fn inner(from: &mut Vec<Token>, close: Token, text: &str) -> Result<Expr> {
+    let expr = list(from)?;
+
+    match from.pop() {
+        Some(token) if token == close => Ok(expr),
+        None => Err(Incomplete.into()),
+        _ => bail!("syntax error: miss `{}`", text),
+    }
+}
+
+/// Takes the keyword, which must be next.
+fn expect(from: &mut Vec<Token>, keyword: Keyword) -> Result<()> {
+    match from.pop() {
+        Some(Token::Keyword(next)) if next == keyword => Ok(()),
+        None => Err(Incomplete.into()),
+        _ => bail!("syntax error: miss `{}`", keyword.as_str()),
+    }
+}
+
+/// `list 'then' list ('elif' list 'then' list)* ('else' list)? 'fi'` after
+/// `if`, `elif` makes a nested `if` in place of `else`.
+fn if_clause(from: &mut Vec<Token>) -> Result<Expr> {
+    let cond = list(from)?;
+    expect(from, Keyword::Then)?;
+    let then = list(from)?;
+
+    let otherwise = match from.pop() {
+        Some(Token::Keyword(Keyword::Fi)) => None,
+        Some(Token::Keyword(Keyword::Elif)) => Some(if_clause(from)?),
+        Some(Token::Keyword(Keyword::Else)) => {
+            let otherwise = list(from)?;
+            expect(from, Keyword::Fi)?;
+            Some(otherwise)
+        }
+        None => return Err(Incomplete.into()),
+        _ => bail!("syntax error: miss `fi`"),
+    };
+
+    Ok(Expr::If {
+        cond: Box::new(cond),
+        then: Box::new(then),
+        otherwise: otherwise.map(Box::new),
+    })
+}
+
+/// `name ('in' words)? ';'? 'do' list 'done'` after `for`, without `in`
+/// the loop goes over positional parameters.
+fn for_clause(from: &mut Vec<Token>) -> Result<Expr> {
+    let mut words = match from.pop() {
+        Some(Token::Cmd(words)) => words.into_iter(),
+        None => return Err(Incomplete.into()),
+        _ => bail!("syntax error: miss variable name of `for`"),
+    };
+    let name = match words.next().map(|word| word.0) {
+        Some(segs) => match segs.as_slice() {
+            [Seg::Bare(name)] if is_name(name) => name.clone(),
+            _ => bail!("`{}`: not a valid identifier", Word(segs)),
+        },
+        None => bail!("syntax error: miss variable name of `for`"),
+    };
+    let items = match words.next() {
+        Some(word) if word.0 == [Seg::Bare("in".to_owned())] => Some(words.collect()),
+        Some(word) => bail!("syntax error near `{}`", word),
+        None => None,
+    };
+
+    if let Some(Token::Redir(Redir::Seq)) = from.last() {
+        from.pop();
+    }
+    expect(from, Keyword::Do)?;
+
+    Ok(Expr::For {
+        name,
+        items,
+        body: Box::new(body(from)?),
+    })
+}
+
+/// `list 'done'` of a loop.
+fn body(from: &mut Vec<Token>) -> Result<Expr> {
+    let body = list(from)?;
+    expect(from, Keyword::Done)?;
+
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokenize(line: &str) -> Vec<Token> {
+        let mut buf = line.to_owned();
+        let line = Line::drain(&mut buf);
+        Vec::try_from(&line).unwrap()
+    }
+
+    fn word(s: &str) -> Word {
+        Word(vec![Seg::Bare(s.into())])
+    }
+
+    fn cat() -> Token {
+        Token::Cmd(vec![word("cat")])
+    }
+
+    #[test]
+    fn write() {
+        let expected = vec![
+            cat(),
+            Token::Redirect(Redirect::Write {
+                fd: 1,
+                path: word("out"),
+                append: false,
+            }),
+        ];
+
+        assert_eq!(tokenize("cat > out"), expected);
+        assert_eq!(tokenize("cat>out"), expected);
+    }
+
+    #[test]
+    fn append() {
+        assert_eq!(
+            tokenize("pwd >> out"),
+            vec![
+                Token::Cmd(vec![word("pwd")]),
+                Token::Redirect(Redirect::Write {
+                    fd: 1,
+                    path: word("out"),
+                    append: true,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn read() {
+        assert_eq!(
+            tokenize("cat < in | cat"),
+            vec![
+                cat(),
+                Token::Redirect(Redirect::Read {
+                    fd: 0,
+                    path: word("in"),
+                }),
+                Token::Redir(Redir::Pipe),
+                cat(),
+            ]
+        );
+    }
+
+    #[test]
+    fn write_stderr() {
+        assert_eq!(
+            tokenize("cat 2> err"),
+            vec![
+                cat(),
+                Token::Redirect(Redirect::Write {
+                    fd: 2,
+                    path: word("err"),
+                    append: false,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn dup_stderr() {
+        assert_eq!(
+            tokenize("cat > out 2>&1"),
+            vec![
+                cat(),
+                Token::Redirect(Redirect::Write {
+                    fd: 1,
+                    path: word("out"),
+                    append: false,
+                }),
+                Token::Redirect(Redirect::Dup { fd: 2, to: 1 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn write_all() {
+        assert_eq!(
+            tokenize("cat &> all &"),
+            vec![
+                cat(),
+                Token::Redirect(Redirect::WriteAll { path: word("all") }),
+                Token::Redir(Redir::Fork),
+            ]
+        );
+    }
+
+    #[test]
+    fn quoted() {
+        assert_eq!(
+            tokenize("echo '2>' \\> \"a | b\""),
+            vec![Token::Cmd(vec![
+                word("echo"),
+                Word(vec![Seg::Single("2>".into())]),
+                Word(vec![Seg::Single(">".into())]),
+                Word(vec![Seg::Double("a | b".into())]),
+            ])]
+        );
+    }
+
+    #[test]
+    fn requote() {
+        let tokens = tokenize(r#"echo \'hi "a\"b" 'c'"#);
+        let Token::Cmd(words) = &tokens[0] else {
+            panic!("{:?}", tokens);
         };
+        let text = Vec::from_iter(words.iter().map(Word::to_string)).join(" ");
+
+        // the words are read back the same
+        assert_eq!(tokenize(&text), tokens);
+    }
+
+    #[test]
+    fn no_target() {
+        let mut buf = "echo >".to_owned();
+        let line = Line::drain(&mut buf);
+        assert!(Vec::<Token>::try_from(&line).is_err());
+    }
+
+    #[test]
+    fn comment() {
+        assert_eq!(
+            tokenize("echo a#b '#' $# # rest | wc"),
+            vec![Token::Cmd(vec![
+                word("echo"),
+                word("a#b"),
+                Word(vec![Seg::Single("#".into())]),
+                word("$#"),
+            ])]
+        );
+    }
+
+    #[test]
+    fn braced_parameter() {
+        assert_eq!(
+            tokenize("echo ${X:-a b}c"),
+            vec![Token::Cmd(vec![word("echo"), word("${X:-a b}c")])]
+        );
+    }
+
+    #[test]
+    fn assignment() {
+        let word = Word(vec![Seg::Bare("X=a".into()), Seg::Double("b c".into())]);
+
+        assert_eq!(
+            word.assignment(),
+            Some((
+                "X",
+                Word(vec![Seg::Bare("a".into()), Seg::Double("b c".into())])
+            ))
+        );
+        assert_eq!(Word(vec![Seg::Single("X=a".into())]).assignment(), None);
+        assert_eq!(self::word("1X=a").assignment(), None);
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(
+            tokenize("a && b || c; d | e &"),
+            vec![
+                Token::Cmd(vec![word("a")]),
+                Token::Redir(Redir::And),
+                Token::Cmd(vec![word("b")]),
+                Token::Redir(Redir::Or),
+                Token::Cmd(vec![word("c")]),
+                Token::Redir(Redir::Seq),
+                Token::Cmd(vec![word("d")]),
+                Token::Redir(Redir::Pipe),
+                Token::Cmd(vec![word("e")]),
+                Token::Redir(Redir::Fork),
+            ]
+        );
+    }
 
-        into.push(expr);
+    #[test]
+    fn precedence() {
+        let mut buf = "a | b && c; d &".to_owned();
+        let line = Line::drain(&mut buf);
+
+        match Expr::try_from(&line).unwrap() {
+            Expr::Seq { left, right } => {
+                assert!(
+                    matches!(*left, Expr::And { left, .. } if matches!(*left, Expr::Pipe { .. }))
+                );
+                assert!(matches!(*right, Expr::Fork { right: None, .. }));
+            }
+            expr => panic!("unexpected {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn brackets() {
+        let mut buf = "(a; b) | { c; } > out && echo }".to_owned();
+        let line = Line::drain(&mut buf);
+
+        match Expr::try_from(&line).unwrap() {
+            Expr::And { left, right } => {
+                match *left {
+                    Expr::Pipe { left, right } => {
+                        assert!(
+                            matches!(*left, Expr::Subshell(expr) if matches!(*expr, Expr::Seq { .. }))
+                        );
+                        assert!(
+                            matches!(*right, Expr::Redirect { expr, .. } if matches!(*expr, Expr::Group(_)))
+                        );
+                    }
+                    expr => panic!("unexpected {:?}", expr),
+                }
+                assert!(
+                    matches!(*right, Expr::Cmd(words) if words == vec![word("echo"), word("}")])
+                );
+            }
+            expr => panic!("unexpected {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn substitution() {
+        assert_eq!(
+            tokenize("cd $(dirname \"$(pwd)\")x \"$(echo ')')\""),
+            vec![Token::Cmd(vec![
+                word("cd"),
+                word("$(dirname \"$(pwd)\")x"),
+                Word(vec![Seg::Double("$(echo ')')".into())]),
+            ])]
+        );
+    }
+
+    #[test]
+    fn functions() {
+        for text in [
+            "f() { a; }; f",
+            "function f { a; }; f",
+            "function f() ( a ); f",
+        ] {
+            let mut buf = text.to_owned();
+            let line = Line::drain(&mut buf);
+
+            match Expr::try_from(&line).unwrap() {
+                Expr::Seq { left, right } => {
+                    assert!(matches!(*left, Expr::Function { name, .. } if name == "f"));
+                    assert!(matches!(*right, Expr::Cmd(words) if words == vec![word("f")]));
+                }
+                expr => panic!("unexpected {:?}", expr),
+            }
+        }
+    }
+
+    #[test]
+    fn control_flow() {
+        let mut buf = "if a; then b; elif c\nthen d; else e; fi\nwhile f; do for x in y z; do g; done; done > out"
+            .to_owned();
+        let line = Line::drain(&mut buf);
+
+        match Expr::try_from(&line).unwrap() {
+            Expr::Seq { left, right } => {
+                match *left {
+                    Expr::If {
+                        otherwise: Some(otherwise),
+                        ..
+                    } => assert!(matches!(
+                        *otherwise,
+                        Expr::If {
+                            otherwise: Some(_),
+                            ..
+                        }
+                    )),
+                    expr => panic!("unexpected {:?}", expr),
+                }
+                match *right {
+                    Expr::Redirect { expr, .. } => match *expr {
+                        Expr::While { body, .. } => assert!(matches!(
+                            *body,
+                            Expr::For { name, items: Some(items), .. } if name == "x" && items.len() == 2
+                        )),
+                        expr => panic!("unexpected {:?}", expr),
+                    },
+                    expr => panic!("unexpected {:?}", expr),
+                }
+            }
+            expr => panic!("unexpected {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn incomplete() {
+        for text in [
+            "if a; then",
+            "for x in y\ndo",
+            "a |",
+            "echo 'a",
+            "{ a",
+            "echo a \\",
+        ] {
+            let mut buf = text.to_owned();
+            let line = Line::drain(&mut buf);
+            assert!(
+                Expr::try_from(&line).unwrap_err().is::<Incomplete>(),
+                "{}",
+                text
+            );
+        }
+
+        let mut buf = "if a; fi".to_owned();
+        let line = Line::drain(&mut buf);
+        assert!(!Expr::try_from(&line).unwrap_err().is::<Incomplete>());
+    }
+
+    #[test]
+    fn unexpected() {
+        for (text, near) in [("echo a ( b", "("), ("(a) b c", "b c"), ("a; }", "}")] {
+            let mut buf = text.to_owned();
+            let line = Line::drain(&mut buf);
+            assert_eq!(
+                Expr::try_from(&line).unwrap_err().to_string(),
+                format!("syntax error near `{}`", near)
+            );
+        }
     }
 }
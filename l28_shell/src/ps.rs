@@ -0,0 +1,174 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use nix::unistd::{sysconf, SysconfVar};
+use std::{fs, io::Write, process};
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Column {
+    Pid,
+    Ppid,
+    State,
+    /// Resident memory in KiB.
+    Rss,
+    Cmdline,
+    /// Time since the process started.
+    Elapsed,
+}
+
+impl Column {
+    fn header(self) -> &'static str {
+        match self {
+            Column::Pid => "PID",
+            Column::Ppid => "PPID",
+            Column::State => "S",
+            Column::Rss => "RSS",
+            Column::Cmdline => "CMD",
+            Column::Elapsed => "ELAPSED",
+        }
+    }
+
+    fn width(self) -> usize {
+        match self {
+            Column::Pid | Column::Ppid => 7,
+            Column::State => 1,
+            Column::Rss => 8,
+            Column::Cmdline => 0,
+            Column::Elapsed => 11,
+        }
+    }
+}
+
+/// Process as described by `/proc/<pid>/stat` and `/proc/<pid>/cmdline`.
+struct Process {
+    pid: i32,
+    ppid: i32,
+    state: char,
+    session: i32,
+    /// Resident pages.
+    rss: u64,
+    /// Clock ticks since boot.
+    start: u64,
+    cmdline: String,
+}
+
+impl Process {
+    fn read(pid: i32) -> Result<Self> {
+        let stat = fs::read_to_string(format!("/proc/{}/stat", pid))?;
+        // The command name is in parentheses and may contain anything.
+        let (comm, rest) = stat
+            .split_once(" (")
+            .and_then(|(_, rest)| rest.rsplit_once(") "))
+            .ok_or(anyhow!("{}: bad stat", pid))?;
+        let fields: Vec<_> = rest.split_whitespace().collect();
+        let field = |i: usize| -> Result<&str> {
+            fields.get(i).copied().ok_or(anyhow!("{}: bad stat", pid))
+        };
+
+        let cmdline = fs::read(format!("/proc/{}/cmdline", pid))?;
+        let cmdline = match cmdline.is_empty() {
+            true => format!("[{}]", comm),
+            false => String::from_utf8_lossy(&cmdline)
+                .trim_end_matches('\0')
+                .replace('\0', " "),
+        };
+
+        // Numbers of fields are in proc(5) minus the first two.
+        Ok(Self {
+            pid,
+            state: field(0)?.chars().next().unwrap_or('?'),
+            ppid: field(1)?.parse()?,
+            session: field(3)?.parse()?,
+            start: field(19)?.parse()?,
+            rss: field(21)?.parse()?,
+            cmdline,
+        })
+    }
+}
+
+/// Writes a table of processes from the session of the shell, or all of
+/// them, with the given columns.
+pub fn ps(all: bool, columns: &[Column], out: &mut dyn Write) -> Result<()> {
+    let page = sysconf(SysconfVar::PAGE_SIZE)?.unwrap_or(4096) as u64;
+    let tick = sysconf(SysconfVar::CLK_TCK)?.unwrap_or(100) as u64;
+    let uptime: f64 = fs::read_to_string("/proc/uptime")?
+        .split_whitespace()
+        .next()
+        .ok_or(anyhow!("bad /proc/uptime"))?
+        .parse()?;
+    let session = Process::read(process::id() as i32)?.session;
+
+    let mut pids: Vec<i32> = fs::read_dir("/proc")?
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        .collect();
+    pids.sort_unstable();
+
+    let header: Vec<_> = columns
+        .iter()
+        .map(|&c| (c, c.header().to_owned()))
+        .collect();
+    write_row(out, &header)?;
+
+    // Processes may exit while being read, those are skipped.
+    for proc in pids.into_iter().filter_map(|pid| Process::read(pid).ok()) {
+        if !all && proc.session != session {
+            continue;
+        }
+
+        let row: Vec<_> = columns
+            .iter()
+            .map(|&c| {
+                let value = match c {
+                    Column::Pid => proc.pid.to_string(),
+                    Column::Ppid => proc.ppid.to_string(),
+                    Column::State => proc.state.to_string(),
+                    Column::Rss => (proc.rss * page / 1024).to_string(),
+                    Column::Cmdline => proc.cmdline.clone(),
+                    Column::Elapsed => elapsed((uptime as u64).saturating_sub(proc.start / tick)),
+                };
+                (c, value)
+            })
+            .collect();
+        write_row(out, &row)?;
+    }
+
+    Ok(())
+}
+
+fn write_row(out: &mut dyn Write, row: &[(Column, String)]) -> Result<()> {
+    let line: Vec<_> = row
+        .iter()
+        .map(|(c, value)| match c {
+            Column::Cmdline => value.clone(),
+            Column::State => format!("{:<1$}", value, c.width()),
+            c => format!("{:>1$}", value, c.width()),
+        })
+        .collect();
+
+    writeln!(out, "{}", line.join(" ").trim_end())?;
+
+    Ok(())
+}
+
+/// Formats seconds as `[[dd-]hh:]mm:ss`, the way `ps` shows `etime`.
+fn elapsed(secs: u64) -> String {
+    let (days, hours, mins, secs) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+
+    match (days, hours) {
+        (0, 0) => format!("{:02}:{:02}", mins, secs),
+        (0, _) => format!("{:02}:{:02}:{:02}", hours, mins, secs),
+        _ => format!("{}-{:02}:{:02}:{:02}", days, hours, mins, secs),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elapsed_format() {
+        assert_eq!(elapsed(5), "00:05");
+        assert_eq!(elapsed(3725), "01:02:05");
+        assert_eq!(elapsed(2 * 86400 + 3725), "2-01:02:05");
+    }
+}
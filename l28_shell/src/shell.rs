@@ -0,0 +1,58 @@
+use crate::{eval::Expr, job::Jobs, vars::Vars};
+use std::{collections::BTreeMap, process};
+
+/// State of the running shell shared by the evaluated expressions.
+#[derive(Debug, Default)]
+pub struct Shell {
+    /// Exit status of the last command, `$?`.
+    pub status: i32,
+    /// Process of the shell itself, `$$`, subshells keep it.
+    pub pid: u32,
+    /// Name of the shell or the script, `$0`.
+    pub name: String,
+    /// Positional parameters, `$1` and on.
+    pub args: Vec<String>,
+    /// `set -e`: exit as soon as a command fails.
+    pub errexit: bool,
+    /// `set -x`: print commands before running them.
+    pub xtrace: bool,
+    pub jobs: Jobs,
+    pub vars: Vars,
+    pub aliases: BTreeMap<String, String>,
+    pub functions: BTreeMap<String, Expr>,
+    /// Aliases being run, which are not replaced again.
+    pub expanding: Vec<String>,
+    /// Nesting of function calls and aliases being run.
+    pub depth: usize,
+}
+
+impl Shell {
+    pub fn new() -> Self {
+        Self {
+            name: env!("CARGO_PKG_NAME").to_owned(),
+            pid: process::id(),
+            vars: Vars::from_env(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Shell {
+    /// Copies the state for a forked child, which has no jobs of its own.
+    pub fn subshell(&self) -> Self {
+        Self {
+            status: self.status,
+            pid: self.pid,
+            name: self.name.clone(),
+            args: self.args.clone(),
+            errexit: self.errexit,
+            xtrace: self.xtrace,
+            jobs: Jobs::default(),
+            vars: self.vars.clone(),
+            aliases: self.aliases.clone(),
+            functions: self.functions.clone(),
+            expanding: self.expanding.clone(),
+            depth: self.depth,
+        }
+    }
+}
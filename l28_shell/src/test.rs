@@ -0,0 +1,245 @@
+use anyhow::{anyhow, bail, Result};
+use nix::unistd::{access, isatty, AccessFlags};
+use std::{
+    fs::{self, Metadata},
+    os::unix::fs::{FileTypeExt, MetadataExt},
+};
+
+/// Evaluates the expression of `test` or `[ ... ]` without the closing
+/// bracket.
+pub fn test(args: &[String]) -> Result<bool> {
+    let mut parser = Parser { args, pos: 0 };
+
+    if args.is_empty() {
+        return Ok(false);
+    }
+
+    let result = parser.or()?;
+
+    match args.get(parser.pos) {
+        None => Ok(result),
+        Some(arg) => bail!("{}: unexpected argument", arg),
+    }
+}
+
+/// Recursive descent over the arguments, from `-o` as the weakest operator
+/// to primaries.
+struct Parser<'a> {
+    args: &'a [String],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self, offset: usize) -> Option<&'a str> {
+        self.args.get(self.pos + offset).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Result<&'a str> {
+        let arg = self
+            .args
+            .get(self.pos)
+            .ok_or(anyhow!("argument expected"))?;
+        self.pos += 1;
+
+        Ok(arg)
+    }
+
+    /// `and ('-o' and)*`
+    fn or(&mut self) -> Result<bool> {
+        let mut result = self.and()?;
+
+        while self.peek(0) == Some("-o") {
+            self.pos += 1;
+            result |= self.and()?;
+        }
+
+        Ok(result)
+    }
+
+    /// `not ('-a' not)*`
+    fn and(&mut self) -> Result<bool> {
+        let mut result = self.not()?;
+
+        while self.peek(0) == Some("-a") {
+            self.pos += 1;
+            result &= self.not()?;
+        }
+
+        Ok(result)
+    }
+
+    /// `'!' not | primary`, unless `!` is the only argument left.
+    fn not(&mut self) -> Result<bool> {
+        match (self.peek(0), self.peek(1)) {
+            (Some("!"), Some(_)) => {
+                self.pos += 1;
+                Ok(!self.not()?)
+            }
+            _ => self.primary(),
+        }
+    }
+
+    /// `string op string | op string | '(' or ')' | string`, a binary operator
+    /// wins over the others, so that `[ -n = -n ]` compares strings.
+    fn primary(&mut self) -> Result<bool> {
+        if let (Some(left), Some(op), Some(right)) = (self.peek(0), self.peek(1), self.peek(2)) {
+            if is_binary(op) {
+                self.pos += 3;
+                return binary(left, op, right);
+            }
+        }
+
+        match (self.peek(0), self.peek(1)) {
+            (Some(op), Some(arg)) if is_unary(op) => {
+                self.pos += 2;
+                unary(op, arg)
+            }
+            (Some("("), Some(_)) => {
+                self.pos += 1;
+                let result = self.or()?;
+                match self.next()? {
+                    ")" => Ok(result),
+                    arg => bail!("{}: `)` expected", arg),
+                }
+            }
+            _ => Ok(!self.next()?.is_empty()),
+        }
+    }
+}
+
+fn is_unary(op: &str) -> bool {
+    matches!(
+        op,
+        "-n" | "-z"
+            | "-e"
+            | "-f"
+            | "-d"
+            | "-r"
+            | "-w"
+            | "-x"
+            | "-s"
+            | "-L"
+            | "-h"
+            | "-p"
+            | "-S"
+            | "-b"
+            | "-c"
+            | "-t"
+    )
+}
+
+fn is_binary(op: &str) -> bool {
+    matches!(
+        op,
+        "=" | "=="
+            | "!="
+            | "<"
+            | ">"
+            | "-eq"
+            | "-ne"
+            | "-lt"
+            | "-le"
+            | "-gt"
+            | "-ge"
+            | "-nt"
+            | "-ot"
+            | "-ef"
+    )
+}
+
+fn unary(op: &str, arg: &str) -> Result<bool> {
+    let meta = |follow: bool| -> Option<Metadata> {
+        match follow {
+            true => fs::metadata(arg).ok(),
+            false => fs::symlink_metadata(arg).ok(),
+        }
+    };
+    let is = |check: fn(&Metadata) -> bool| meta(true).as_ref().is_some_and(check);
+
+    let result = match op {
+        "-n" => !arg.is_empty(),
+        "-z" => arg.is_empty(),
+        "-e" => meta(true).is_some(),
+        "-f" => is(Metadata::is_file),
+        "-d" => is(Metadata::is_dir),
+        "-s" => is(|meta| meta.len() > 0),
+        "-p" => is(|meta| meta.file_type().is_fifo()),
+        "-S" => is(|meta| meta.file_type().is_socket()),
+        "-b" => is(|meta| meta.file_type().is_block_device()),
+        "-c" => is(|meta| meta.file_type().is_char_device()),
+        "-L" | "-h" => meta(false).is_some_and(|meta| meta.is_symlink()),
+        "-r" => access(arg, AccessFlags::R_OK).is_ok(),
+        "-w" => access(arg, AccessFlags::W_OK).is_ok(),
+        "-x" => access(arg, AccessFlags::X_OK).is_ok(),
+        "-t" => isatty(integer(arg)?).unwrap_or(false),
+        _ => unreachable!(),
+    };
+
+    Ok(result)
+}
+
+fn binary(left: &str, op: &str, right: &str) -> Result<bool> {
+    let modified = |path: &str| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+
+    let result = match op {
+        "=" | "==" => left == right,
+        "!=" => left != right,
+        "<" => left < right,
+        ">" => left > right,
+        "-eq" => integer(left)? == integer(right)?,
+        "-ne" => integer(left)? != integer(right)?,
+        "-lt" => integer(left)? < integer(right)?,
+        "-le" => integer(left)? <= integer(right)?,
+        "-gt" => integer(left)? > integer(right)?,
+        "-ge" => integer(left)? >= integer(right)?,
+        // A missing file is older than any existing one.
+        "-nt" => modified(left) > modified(right),
+        "-ot" => modified(left) < modified(right),
+        "-ef" => match (fs::metadata(left), fs::metadata(right)) {
+            (Ok(left), Ok(right)) => (left.dev(), left.ino()) == (right.dev(), right.ino()),
+            _ => false,
+        },
+        _ => unreachable!(),
+    };
+
+    Ok(result)
+}
+
+fn integer(arg: &str) -> Result<i32> {
+    arg.trim()
+        .parse()
+        .map_err(|_| anyhow!("{}: integer expression expected", arg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(text: &str) -> Result<bool> {
+        let args: Vec<_> = text.split_whitespace().map(str::to_owned).collect();
+        test(&args)
+    }
+
+    #[test]
+    fn strings_and_integers() {
+        assert!(eval("abc").unwrap());
+        assert!(!eval("").unwrap());
+        assert!(eval("-n").unwrap());
+        assert!(eval("a = a").unwrap());
+        assert!(eval("a != b").unwrap());
+        assert!(eval("10 -gt 9").unwrap());
+        assert!(!eval("1 -eq 2").unwrap());
+        assert!(eval("x -lt 1").is_err());
+    }
+
+    #[test]
+    fn operators() {
+        assert!(eval("! a = b").unwrap());
+        assert!(eval("a = b -o 1 -le 1").unwrap());
+        assert!(!eval("a = a -a ( 1 -eq 2 -o ! x )").unwrap());
+        assert!(eval("-z a -o -n a -a a").unwrap());
+        assert!(eval("-d / -a ! -f /").unwrap());
+        assert!(eval("( a").is_err());
+        assert!(eval("a b").is_err());
+    }
+}
@@ -0,0 +1,80 @@
+use std::{collections::BTreeMap, env};
+
+#[derive(Debug, Clone)]
+struct Var {
+    value: String,
+    exported: bool,
+}
+
+/// Shell variables, the exported ones make up the environment of commands.
+#[derive(Debug, Clone, Default)]
+pub struct Vars(BTreeMap<String, Var>);
+
+impl Vars {
+    /// Imports the environment of the shell process.
+    pub fn from_env() -> Self {
+        Self(
+            env::vars()
+                .map(|(name, value)| {
+                    let var = Var {
+                        value,
+                        exported: true,
+                    };
+                    (name, var)
+                })
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(|var| var.value.as_str())
+    }
+
+    /// Sets the value keeping the variable exported if it was.
+    pub fn set(&mut self, name: &str, value: String) {
+        match self.0.get_mut(name) {
+            Some(var) => var.value = value,
+            None => {
+                let var = Var {
+                    value,
+                    exported: false,
+                };
+                self.0.insert(name.to_owned(), var);
+            }
+        }
+    }
+
+    /// Marks the variable for export, setting its value if given.
+    pub fn export(&mut self, name: &str, value: Option<String>) {
+        let var = self.0.entry(name.to_owned()).or_insert(Var {
+            value: String::new(),
+            exported: true,
+        });
+
+        var.exported = true;
+        if let Some(value) = value {
+            var.value = value;
+        }
+    }
+
+    pub fn unset(&mut self, name: &str) {
+        self.0.remove(name);
+    }
+
+    pub fn exported(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .filter(|(_, var)| var.exported)
+            .map(|(name, var)| (name.as_str(), var.value.as_str()))
+    }
+}
+
+/// Checks that `name` is a valid variable name.
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars
+        .next()
+        .is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
@@ -0,0 +1,198 @@
+use nix::{
+    errno::Errno,
+    fcntl::{fcntl, FcntlArg, OFlag},
+    libc,
+    pty::openpty,
+    unistd::{read, setsid, tcgetpgrp, write, Pid},
+};
+use std::{
+    os::{
+        fd::{AsRawFd, OwnedFd},
+        unix::process::CommandExt,
+    },
+    path::Path,
+    process::{Child, Command},
+    thread,
+    time::{Duration, Instant},
+};
+use tempfile::TempDir;
+
+/// How long to wait for the shell to show a prompt.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Erases the rest of the line, the editor ends every rendered prompt with it.
+const ERASE: &str = "\x1b[K";
+
+/// Interactive shell running on a pseudo-terminal in a temporary home
+/// directory, which is also the current one.
+pub struct Session {
+    master: OwnedFd,
+    child: Child,
+    home: TempDir,
+    /// Output not taken yet.
+    buf: String,
+    /// Status shown by the last prompt, `None` for a continuation one.
+    status: Option<i32>,
+}
+
+impl Session {
+    /// Starts the shell and waits for its first prompt.
+    pub fn spawn() -> Self {
+        let home = tempfile::tempdir().expect("temporary home");
+        let pty = openpty(None, None).expect("pty");
+        let slave = pty.slave;
+
+        let mut command = Command::new(env!("CARGO_BIN_EXE_l28_shell"));
+        command
+            .current_dir(home.path())
+            .env_clear()
+            .env("PATH", std::env::var("PATH").unwrap_or_default())
+            .env("HOME", home.path())
+            .env("TERM", "dumb")
+            .stdin(slave.try_clone().expect("dup"))
+            .stdout(slave.try_clone().expect("dup"))
+            .stderr(slave);
+
+        // The terminal becomes the controlling one of a new session, so that
+        // the shell gets job control.
+        unsafe {
+            command.pre_exec(|| {
+                setsid()?;
+                if libc::ioctl(0, libc::TIOCSCTTY, 0) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = command.spawn().expect("shell binary");
+        drop(command);
+
+        let flags = fcntl(pty.master.as_raw_fd(), FcntlArg::F_GETFL).expect("flags");
+        let flags = OFlag::from_bits_truncate(flags) | OFlag::O_NONBLOCK;
+        fcntl(pty.master.as_raw_fd(), FcntlArg::F_SETFL(flags)).expect("non-blocking");
+
+        let mut session = Self {
+            master: pty.master,
+            child,
+            home,
+            buf: String::new(),
+            status: None,
+        };
+        session.wait_prompt();
+
+        session
+    }
+
+    /// Home and initial current directory of the shell.
+    pub fn home(&self) -> &Path {
+        self.home.path()
+    }
+
+    /// Types the text as is, control characters included.
+    pub fn send(&mut self, text: &str) {
+        let mut bytes = text.as_bytes();
+
+        while !bytes.is_empty() {
+            match write(&self.master, bytes) {
+                Ok(n) => bytes = &bytes[n..],
+                Err(Errno::EAGAIN) => thread::sleep(Duration::from_millis(10)),
+                Err(why) => panic!("write to pty: {}", why),
+            }
+        }
+    }
+
+    /// Types the line and presses Enter.
+    pub fn send_line(&mut self, line: &str) {
+        self.send(line);
+        self.send("\r");
+    }
+
+    /// Types the keys once a command, not the shell, is in the foreground,
+    /// so that the terminal turns them into signals.
+    pub fn send_to_command(&mut self, keys: &str) {
+        let shell = Pid::from_raw(self.child.id() as i32);
+        let deadline = Instant::now() + TIMEOUT;
+
+        while tcgetpgrp(&self.master).is_ok_and(|pgrp| pgrp == shell) {
+            if Instant::now() > deadline {
+                panic!("no command in the foreground, got {:?}", self.buf);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        self.send(keys);
+    }
+
+    /// Runs the line and returns its output, see [`Session::wait_prompt`].
+    pub fn run(&mut self, line: &str) -> String {
+        self.send_line(line);
+        self.wait_prompt()
+    }
+
+    /// Waits for the next prompt, either the main or a continuation one,
+    /// and returns the output since the line was entered, with `\n` line
+    /// ends.
+    pub fn wait_prompt(&mut self) -> String {
+        let deadline = Instant::now() + TIMEOUT;
+
+        loop {
+            if let Some(output) = self.take_prompt() {
+                return output;
+            }
+            if Instant::now() > deadline {
+                panic!("no prompt in time, got {:?}", self.buf);
+            }
+
+            let mut chunk = [0; 4096];
+            match read(self.master.as_raw_fd(), &mut chunk) {
+                Ok(0) | Err(Errno::EIO) => panic!("shell exited, got {:?}", self.buf),
+                Ok(n) => self.buf.push_str(&String::from_utf8_lossy(&chunk[..n])),
+                Err(Errno::EAGAIN) => thread::sleep(Duration::from_millis(10)),
+                Err(why) => panic!("read from pty: {}", why),
+            }
+        }
+    }
+
+    /// Status shown by the last prompt.
+    pub fn status(&self) -> i32 {
+        self.status.expect("continuation prompt has no status")
+    }
+
+    /// Takes the output up to an empty prompt, whose rendering starts with
+    /// `\r` and ends with `$ ` or `> ` before the erase sequence. Renderings
+    /// of the typed line come before its `\r\n` and are skipped.
+    fn take_prompt(&mut self) -> Option<String> {
+        let mut from = 0;
+
+        loop {
+            let end = from + self.buf[from..].find(ERASE)?;
+            let head = &self.buf[..end];
+            from = end + ERASE.len();
+
+            let (status, prompt) = if head.ends_with("]$ ") {
+                let open = head.rfind('[')?;
+                (head[open + 1..end - 3].parse().ok(), head.rfind('\r')?)
+            } else if head.ends_with("\r> ") {
+                (None, end - 3)
+            } else {
+                continue;
+            };
+
+            let output = match self.buf[..prompt].find("\r\n") {
+                Some(enter) => self.buf[enter + 2..prompt].replace("\r\n", "\n"),
+                None => String::new(),
+            };
+            self.buf.drain(..from);
+            self.status = status;
+
+            return Some(output);
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
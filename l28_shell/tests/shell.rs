@@ -0,0 +1,208 @@
+mod common;
+
+use common::Session;
+use std::fs;
+
+#[test]
+fn builtins() {
+    let mut sh = Session::spawn();
+    fs::create_dir(sh.home().join("sub")).unwrap();
+
+    assert_eq!(sh.run("echo hello"), "hello\n");
+    assert_eq!(sh.status(), 0);
+
+    sh.run("cd sub");
+    let pwd = sh.home().join("sub").canonicalize().unwrap();
+    assert_eq!(sh.run("pwd"), format!("{}\n", pwd.display()));
+    sh.run("cd");
+    assert_eq!(sh.run("pwd"), format!("{}\n", sh.home().display()));
+
+    sh.run("export GREETING=hi");
+    assert_eq!(sh.run("env | grep ^GREETING="), "GREETING=hi\n");
+    assert_eq!(
+        sh.run("GREETING=hey ONCE=1 env | grep -e ^GREETING= -e ^ONCE="),
+        "GREETING=hey\nONCE=1\n"
+    );
+    sh.run("unset GREETING");
+    assert_eq!(sh.run("echo \"[$GREETING]\""), "[]\n");
+
+    sh.run("alias hi='echo alias'");
+    assert_eq!(sh.run("hi"), "alias\n");
+    sh.run("greet() { echo \"hello $1\"; return 3; }");
+    assert_eq!(sh.run("greet you"), "hello you\n");
+    assert_eq!(sh.status(), 3);
+}
+
+#[test]
+fn expansions() {
+    let mut sh = Session::spawn();
+    for name in ["a.txt", "b.txt", "c.md"] {
+        fs::write(sh.home().join(name), "").unwrap();
+    }
+
+    assert_eq!(sh.run("echo *.txt"), "a.txt b.txt\n");
+    assert_eq!(sh.run("echo '*.txt' x{1,2}"), "*.txt x1 x2\n");
+    assert_eq!(sh.run("X='a  b'; echo $X \"$X\""), "a b a  b\n");
+    assert_eq!(sh.run("echo $(echo inner | tr a-z A-Z)"), "INNER\n");
+    assert_eq!(sh.run("echo x$(echo {a,b})"), "xa b\n");
+    assert_eq!(sh.run("false; echo $?"), "1\n");
+
+    // subshells and substitutions see the pid of the shell itself
+    let pid = sh.run("echo $$");
+    assert_eq!(sh.run("(echo $$)"), pid);
+    assert_eq!(sh.run("echo $(echo $$)"), pid);
+    assert_eq!(sh.run("echo $$ | cat"), pid);
+}
+
+#[test]
+fn pipes_and_redirects() {
+    let mut sh = Session::spawn();
+
+    assert_eq!(sh.run("echo abc | tr a-z A-Z"), "ABC\n");
+    assert_eq!(sh.run("printf 'b\\na\\nb\\n' | sort | uniq | wc -l"), "2\n");
+    assert_eq!(sh.run("pwd | wc -l"), "1\n");
+
+    sh.run("echo one > out; echo two >> out");
+    assert_eq!(
+        fs::read_to_string(sh.home().join("out")).unwrap(),
+        "one\ntwo\n"
+    );
+    assert_eq!(sh.run("cat < out | head -1"), "one\n");
+    assert_eq!(sh.run("ls nowhere 2> err; wc -l < err"), "1\n");
+}
+
+#[test]
+fn lists() {
+    let mut sh = Session::spawn();
+
+    assert_eq!(sh.run("true && echo yes || echo no"), "yes\n");
+    assert_eq!(sh.run("false && echo yes || echo no"), "no\n");
+    assert_eq!(sh.run("echo a; echo b"), "a\nb\n");
+    assert_eq!(sh.run("(cd /; pwd); { echo group; }"), "/\ngroup\n");
+}
+
+#[test]
+fn forks() {
+    let mut sh = Session::spawn();
+
+    let started = sh.run("sleep 1 &");
+    assert!(started.starts_with("[1] "), "{:?}", started);
+
+    assert_eq!(sh.run("jobs"), "[1]+  Running\tsleep 1\n");
+    sh.run("wait");
+    assert_eq!(sh.status(), 0);
+    assert_eq!(sh.run("jobs"), "");
+
+    // The background job writes after the foreground one waits for it.
+    assert_eq!(
+        sh.run("(sleep 0.1; echo bg) & echo fg; wait")
+            .lines()
+            .last(),
+        Some("bg")
+    );
+}
+
+#[test]
+fn job_control() {
+    let mut sh = Session::spawn();
+
+    sh.send_line("sleep 10");
+    sh.send_to_command("\x03");
+    sh.wait_prompt();
+    assert_eq!(sh.status(), 130);
+
+    sh.send_line("sleep 10");
+    sh.send_to_command("\x1a");
+    let stopped = sh.wait_prompt();
+    assert!(stopped.contains("[1]+  Stopped\tsleep 10"), "{:?}", stopped);
+
+    // A stopped job is reported instead of waited for.
+    assert_eq!(sh.run("wait %1"), "[1]+  Stopped\tsleep 10\n");
+    assert_eq!(sh.status(), 148);
+    assert_eq!(sh.run("jobs"), "[1]+  Stopped\tsleep 10\n");
+
+    sh.run("kill %1");
+    sh.run("wait");
+    assert_eq!(sh.run("jobs"), "");
+}
+
+#[test]
+fn control_flow() {
+    let mut sh = Session::spawn();
+
+    assert_eq!(sh.run("for x in a b; do echo $x; done"), "a\nb\n");
+    assert_eq!(
+        sh.run("n=0; while [ $n -lt 2 ]; do echo $n; n=$(expr $n + 1); done"),
+        "0\n1\n"
+    );
+
+    // Unfinished constructs continue on the next lines.
+    assert_eq!(sh.run("if [ -d / ]"), "");
+    assert_eq!(sh.run("then echo dir"), "");
+    assert_eq!(sh.run("else echo other"), "");
+    assert_eq!(sh.run("fi"), "dir\n");
+    assert_eq!(sh.status(), 0);
+
+    assert_eq!(sh.run("echo 'a"), "");
+    assert_eq!(sh.run("b'"), "a\nb\n");
+}
+
+#[test]
+fn errors() {
+    let mut sh = Session::spawn();
+
+    assert_eq!(
+        sh.run("no_such_command"),
+        "error: no_such_command: command not found\n"
+    );
+    assert_eq!(sh.status(), 127);
+
+    assert!(sh.run("cd /nowhere").starts_with("error: "));
+    assert_eq!(sh.status(), 1);
+
+    assert_eq!(
+        sh.run("cat < missing"),
+        "error: missing: No such file or directory (os error 2)\n"
+    );
+    assert_eq!(sh.run("echo )"), "error: syntax error near `)`\n");
+    assert_eq!(sh.run("| cat"), "error: syntax error: miss command\n");
+    assert_eq!(
+        sh.run("[ 1 -eq x ]"),
+        "error: test: x: integer expression expected\n"
+    );
+    assert_eq!(sh.status(), 2);
+
+    // The shell keeps working after errors.
+    assert_eq!(sh.run("echo still here"), "still here\n");
+}
+
+#[test]
+fn completion() {
+    let mut sh = Session::spawn();
+    fs::write(sh.home().join("my file's.txt"), "content\n").unwrap();
+
+    // the completed name is escaped, so it stays one word
+    sh.send("cat my\t");
+    assert_eq!(sh.run(""), "content\n");
+    sh.send("cat my\\ f\t");
+    assert_eq!(sh.run(""), "content\n");
+}
+
+#[test]
+fn history() {
+    let mut sh = Session::spawn();
+
+    sh.send_line("for i in 1 2");
+    sh.wait_prompt();
+    sh.send_line("do echo $i");
+    sh.wait_prompt();
+    assert_eq!(sh.run("done"), "1\n2\n");
+
+    // the command of several lines is one entry
+    let saved = fs::read_to_string(sh.home().join(".l28_shell_history")).unwrap();
+    assert_eq!(saved, "for i in 1 2\\\ndo echo $i\\\ndone\n");
+
+    // recalled, it is rendered on several rows before it runs
+    sh.send("\x1b[A");
+    assert!(sh.run("").ends_with("1\n2\n"));
+}